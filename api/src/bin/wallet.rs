@@ -0,0 +1,293 @@
+use std::{error::Error, fs, io::Read, path::PathBuf};
+
+use blockchain::{
+  core::{
+    amount::Amount,
+    network::Network,
+    raw_transaction::RawTransaction,
+    transaction::Transaction,
+    wallet::{hd::HdWallet, keystore::Keystore, Wallet},
+  },
+  utils::{address::{self, AddressFormat}, serializable::Serializable},
+};
+use clap::{Args, Parser, Subcommand};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// Manage keys and transfers without handing private keys to a node.
+#[derive(Parser)]
+#[command(name = "wallet", version)]
+struct Cli {
+  /// print machine readable JSON instead of text
+  #[arg(long, global = true)]
+  json: bool,
+
+  #[command(subcommand)]
+  command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+  /// Create a new HD wallet and print its recovery phrase
+  Generate {
+    #[arg(long, default_value_t = Network::Mainnet)]
+    network: Network,
+    #[command(flatten)]
+    seed: SeedArgs,
+    #[command(flatten)]
+    save: SaveArgs,
+  },
+  /// Derive a receiving address of the wallet behind a recovery phrase
+  Recover {
+    #[arg(long, env = "WALLET_MNEMONIC", hide_env_values = true)]
+    mnemonic: String,
+    #[arg(long, default_value_t = Network::Mainnet)]
+    network: Network,
+    /// index of the receiving address
+    #[arg(long, default_value_t = 0)]
+    index: u32,
+    #[command(flatten)]
+    seed: SeedArgs,
+    #[command(flatten)]
+    save: SaveArgs,
+  },
+  /// Show the address of a keystore in every format, no password needed
+  Address {
+    #[arg(long)]
+    keystore: PathBuf,
+  },
+  /// Ask a node for the balance of an address
+  Balance {
+    address: String,
+    #[arg(long, default_value = "http://127.0.0.1:8000")]
+    node: String,
+  },
+  /// Sign a transfer offline, the nonce and chain id come from `GET /nonce/{address}`
+  Sign {
+    #[command(flatten)]
+    unlock: UnlockArgs,
+    #[arg(long)]
+    to: String,
+    #[arg(long)]
+    amount: Amount,
+    #[arg(long)]
+    nonce: u64,
+    #[arg(long)]
+    chain_id: String,
+  },
+  /// Submit a transaction printed by `sign` to a node
+  Broadcast {
+    /// file holding the output of `sign --json`, stdin when left out
+    file: Option<PathBuf>,
+    #[arg(long, default_value = "http://127.0.0.1:8000")]
+    node: String,
+  },
+}
+
+#[derive(Args)]
+struct SeedArgs {
+  /// optional BIP39 passphrase, a different passphrase derives a different wallet
+  #[arg(long, env = "WALLET_PASSPHRASE", hide_env_values = true, default_value = "")]
+  passphrase: String,
+}
+
+#[derive(Args)]
+struct SaveArgs {
+  /// write the derived key, the one at `--index` for `recover`, to this keystore file
+  #[arg(long, requires = "password")]
+  keystore: Option<PathBuf>,
+  #[arg(long, env = "WALLET_PASSWORD", hide_env_values = true)]
+  password: Option<String>,
+}
+
+#[derive(Args)]
+struct UnlockArgs {
+  #[arg(long)]
+  keystore: PathBuf,
+  #[arg(long, env = "WALLET_PASSWORD", hide_env_values = true)]
+  password: String,
+}
+
+/// Signed transfer as printed by `sign`, `raw` is what `broadcast` submits.
+#[derive(Serialize)]
+struct SignedOutput {
+  transaction: Transaction,
+  raw: String,
+}
+
+#[tokio::main]
+async fn main() {
+  let cli = Cli::parse();
+
+  match run(cli.command).await {
+    Ok(output) => print(&output, cli.json),
+    Err(err) => {
+      if cli.json {
+        println!("{}", json!({ "error": err.to_string() }));
+      } else {
+        eprintln!("error: {}", err);
+      }
+
+      std::process::exit(1);
+    }
+  }
+}
+
+async fn run(command: Command) -> Result<Value, Box<dyn Error>> {
+  match command {
+    Command::Generate { network, seed, save } => {
+      let mnemonic = HdWallet::generate(network).mnemonic();
+      let hd_wallet = HdWallet::from_mnemonic(&mnemonic, &seed.passphrase, network)?;
+
+      let mut output = describe_hd_wallet(&hd_wallet, 0, &save)?;
+      output["mnemonic"] = json!(mnemonic);
+
+      Ok(output)
+    }
+    Command::Recover { mnemonic, network, index, seed, save } => {
+      let hd_wallet = HdWallet::from_mnemonic(&mnemonic, &seed.passphrase, network)?;
+
+      describe_hd_wallet(&hd_wallet, index, &save)
+    }
+    Command::Address { keystore } => {
+      let keystore = Keystore::from_file(keystore)?;
+
+      Ok(json!({
+        "address": keystore.address,
+        "bech32_address": address::convert(&keystore.address, AddressFormat::Bech32)?,
+        "network": address::validate(&keystore.address)?,
+      }))
+    }
+    Command::Balance { address, node } => {
+      address::validate(&address)?;
+
+      let response = reqwest::get(format!("{}/amount/{}", node.trim_end_matches('/'), address)).await?;
+      let body = node_response(response).await?;
+
+      Ok(json!({ "address": address, "amount": body["amount"] }))
+    }
+    Command::Sign { unlock, to, amount, nonce, chain_id } => {
+      address::validate(&to)?;
+
+      let wallet = Wallet::import_keystore(&unlock.keystore, &unlock.password)?;
+
+      Ok(serde_json::to_value(sign(&wallet, to, amount, nonce, &chain_id)?)?)
+    }
+    Command::Broadcast { file, node } => {
+      let content = match file {
+        Some(file) => fs::read_to_string(file)?,
+        None => {
+          let mut content = String::new();
+          std::io::stdin().read_to_string(&mut content)?;
+          content
+        }
+      };
+
+      let signed: Value = serde_json::from_str(&content)?;
+      let raw = signed["raw"].as_str().ok_or("input has no \"raw\" transaction, pass the output of `sign --json`")?;
+
+      let response = reqwest::Client::new()
+        .post(format!("{}/transactions", node.trim_end_matches('/')))
+        .json(&json!({ "raw": raw }))
+        .send()
+        .await?;
+
+      Ok(json!({ "node": node, "result": node_response(response).await? }))
+    }
+  }
+}
+
+fn describe_hd_wallet(hd_wallet: &HdWallet, index: u32, save: &SaveArgs) -> Result<Value, Box<dyn Error>> {
+  let wallet = hd_wallet.wallet(index)?;
+
+  if let (Some(path), Some(password)) = (&save.keystore, &save.password) {
+    wallet.export_keystore(path, password)?;
+  }
+
+  Ok(json!({
+    "path": hd_wallet.receiving_path(index)?.to_string(),
+    "address": wallet.address(),
+    "bech32_address": wallet.address_in(AddressFormat::Bech32),
+    "public_key": wallet.public_key(),
+    "keystore": save.keystore,
+  }))
+}
+
+fn sign(wallet: &Wallet, to: String, amount: Amount, nonce: u64, chain_id: &str) -> Result<SignedOutput, Box<dyn Error>> {
+  let transaction = wallet.sign_transaction(to, amount, nonce, chain_id);
+  let raw = hex::encode(RawTransaction::from_transaction(&transaction)?.serialize());
+
+  Ok(SignedOutput { transaction, raw })
+}
+
+/// Body of a node response, or the node's error message.
+async fn node_response(response: reqwest::Response) -> Result<Value, Box<dyn Error>> {
+  let status = response.status();
+  let body: Value = response.json().await?;
+
+  if !status.is_success() {
+    let message = body["message"].as_str().map(str::to_string).unwrap_or_else(|| body.to_string());
+
+    return Err(format!("node answered {}: {}", status, message).into());
+  }
+
+  Ok(body)
+}
+
+fn print(output: &Value, json: bool) {
+  if json {
+    println!("{}", serde_json::to_string_pretty(output).unwrap());
+
+    return;
+  }
+
+  let Some(fields) = output.as_object() else {
+    println!("{}", output);
+
+    return;
+  };
+
+  for (name, value) in fields {
+    match value {
+      Value::Null => continue,
+      Value::String(text) => println!("{}: {}", name, text),
+      value => println!("{}: {}", name, value),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use blockchain::{
+    core::{amount::Amount, network::Network, raw_transaction::RawTransaction, wallet::{hd::HdWallet, Wallet}},
+    utils::serializable::Serializable,
+  };
+
+  use super::{describe_hd_wallet, sign, SaveArgs};
+
+  #[test]
+  fn test_signed_raw_output_verifies() {
+    let wallet = Wallet::new(Network::Testnet);
+    let to = Wallet::new(Network::Testnet).address();
+    let signed = sign(&wallet, to.clone(), "1.5".parse::<Amount>().unwrap(), 3, "test").unwrap();
+
+    let raw = hex::decode(&signed.raw).unwrap();
+    let transaction = <RawTransaction as Serializable<_>>::deserialize(&raw).unwrap().to_transaction().unwrap();
+
+    assert_eq!(transaction.sender, wallet.address());
+    assert_eq!(transaction.receiver, to);
+    assert_eq!(Wallet::verify_transaction(&transaction), Ok(()));
+  }
+
+  #[test]
+  fn test_recover_uses_passphrase_and_hides_mnemonic() {
+    let mnemonic = HdWallet::generate(Network::Testnet).mnemonic();
+    let save = SaveArgs { keystore: None, password: None };
+
+    let plain = describe_hd_wallet(&HdWallet::from_mnemonic(&mnemonic, "", Network::Testnet).unwrap(), 0, &save).unwrap();
+    let protected = describe_hd_wallet(&HdWallet::from_mnemonic(&mnemonic, "extra", Network::Testnet).unwrap(), 0, &save).unwrap();
+
+    assert_ne!(plain["address"], protected["address"]);
+    assert!(plain.get("mnemonic").is_none());
+  }
+}
@@ -1 +1,3 @@
-pub mod server;
\ No newline at end of file
+pub mod server;
+pub mod error;
+pub mod config;
\ No newline at end of file
@@ -0,0 +1,216 @@
+use std::{fs, io, net::SocketAddr, path::{Path, PathBuf}};
+
+use blockchain::{core::{error::{AddressError, BlockchainError}, network::Network}, utils::address};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Reason the node configuration could not be loaded.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+  #[error("failed to read config {path}: {source}")]
+  Read { path: PathBuf, source: io::Error },
+  #[error("invalid config {path}: {source}")]
+  Parse { path: PathBuf, source: toml::de::Error },
+  #[error("invalid miner address: {0}")]
+  MinerAddress(#[from] AddressError),
+  #[error("set miner_password, or miner_unencrypted to store the miner wallet without one")]
+  MissingMinerPassword,
+  #[error("failed to load miner wallet: {0}")]
+  MinerWallet(#[from] BlockchainError),
+  #[error("failed to set up the miner in {path}: {source}")]
+  Miner { path: PathBuf, source: Box<ConfigError> },
+  #[error("failed to load genesis spec {path}: {source}")]
+  Genesis { path: PathBuf, source: io::Error },
+  #[error("genesis spec {path} is for {found}, node runs on {expected}")]
+  GenesisNetwork { path: PathBuf, expected: Network, found: Network },
+  #[error("failed to open blockchain in {path}: {source}")]
+  Storage { path: PathBuf, source: BlockchainError },
+}
+
+/// Settings of a single node. Read from a TOML file, every field can be
+/// overridden by a `NODE_*` environment variable or a command line flag.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeConfig {
+  pub network: Network,
+  /// `0.0.0.0` on the network's default port when left out
+  pub listen: Option<SocketAddr>,
+  /// `data/{network}/node-{port}` when left out
+  pub data_dir: Option<PathBuf>,
+  /// `host:port` of the nodes pinged on start up
+  pub seed_peers: Vec<String>,
+  /// address mining rewards are paid to, the wallet in `miner.json` of the
+  /// data directory when left out
+  pub miner_address: Option<String>,
+  /// password `miner.json` is encrypted with
+  pub miner_password: Option<String>,
+  /// create `miner.json` with an empty password when `miner_password` is left out
+  pub miner_unencrypted: bool,
+  /// `env_logger` filter, e.g. `info` or `api=debug,actix_web=warn`
+  pub log_level: String,
+  /// genesis spec file, the network's built in genesis when left out
+  pub genesis: Option<PathBuf>,
+  /// seconds between two rounds of pinging the peers
+  pub peer_sync_secs: u64,
+}
+
+/// Command line flags of the node, each falling back to its environment variable.
+#[derive(Args, Debug, Default)]
+pub struct NodeArgs {
+  /// TOML file to read the settings from
+  #[arg(long, short, env = "NODE_CONFIG")]
+  pub config: Option<PathBuf>,
+  #[arg(long, env = "NODE_NETWORK")]
+  pub network: Option<Network>,
+  #[arg(long, env = "NODE_LISTEN")]
+  pub listen: Option<SocketAddr>,
+  #[arg(long, env = "NODE_DATA_DIR")]
+  pub data_dir: Option<PathBuf>,
+  /// may be repeated or given as a comma separated list
+  #[arg(long = "seed-peer", env = "NODE_SEED_PEERS", value_delimiter = ',')]
+  pub seed_peers: Vec<String>,
+  #[arg(long, env = "NODE_MINER_ADDRESS")]
+  pub miner_address: Option<String>,
+  #[arg(long, env = "NODE_MINER_PASSWORD", hide_env_values = true)]
+  pub miner_password: Option<String>,
+  #[arg(long, env = "NODE_MINER_UNENCRYPTED")]
+  pub miner_unencrypted: bool,
+  #[arg(long, env = "NODE_LOG_LEVEL")]
+  pub log_level: Option<String>,
+  #[arg(long, env = "NODE_GENESIS")]
+  pub genesis: Option<PathBuf>,
+  #[arg(long, env = "NODE_PEER_SYNC_SECS")]
+  pub peer_sync_secs: Option<u64>,
+}
+
+impl NodeConfig {
+  const DATA_DIR: &'static str = "data";
+  /// default ports tried on this host when no seed peers are configured
+  const LOCAL_PEER_COUNT: u16 = 4;
+
+  pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path).map_err(|source| ConfigError::Read { path: path.to_path_buf(), source })?;
+
+    toml::from_str(&content).map_err(|source| ConfigError::Parse { path: path.to_path_buf(), source })
+  }
+
+  /// The config file named by `args`, if any, with the flags applied on top.
+  pub fn load(args: NodeArgs) -> Result<Self, ConfigError> {
+    let mut config = match &args.config {
+      Some(path) => Self::from_file(path)?,
+      None => Self::default(),
+    };
+
+    config.network = args.network.unwrap_or(config.network);
+    config.listen = args.listen.or(config.listen);
+    config.data_dir = args.data_dir.or(config.data_dir);
+    config.miner_address = args.miner_address.or(config.miner_address);
+    config.miner_password = args.miner_password.or(config.miner_password);
+    config.miner_unencrypted |= args.miner_unencrypted;
+    config.log_level = args.log_level.unwrap_or(config.log_level);
+    config.genesis = args.genesis.or(config.genesis);
+    config.peer_sync_secs = args.peer_sync_secs.unwrap_or(config.peer_sync_secs);
+
+    if !args.seed_peers.is_empty() {
+      config.seed_peers = args.seed_peers;
+    }
+
+    if let Some(miner_address) = config.miner_address.as_ref() {
+      address::validate_for(miner_address, config.network)?;
+    }
+
+    Ok(config)
+  }
+
+  pub fn listen(&self) -> SocketAddr {
+    self.listen.unwrap_or_else(|| SocketAddr::from(([0, 0, 0, 0], self.network.default_port())))
+  }
+
+  pub fn data_dir(&self) -> PathBuf {
+    self.data_dir
+      .clone()
+      .unwrap_or_else(|| Path::new(Self::DATA_DIR).join(self.network.to_string()).join(format!("node-{}", self.listen().port())))
+  }
+
+  /// The configured seed peers, or the other default ports on this host so a
+  /// few nodes started without a config still find each other.
+  pub fn peers(&self) -> Vec<String> {
+    if !self.seed_peers.is_empty() {
+      return self.seed_peers.clone();
+    }
+
+    let port = self.listen().port();
+
+    (0..Self::LOCAL_PEER_COUNT)
+      .map(|offset| self.network.default_port() + offset)
+      .filter(|peer_port| *peer_port != port)
+      .map(|peer_port| format!("127.0.0.1:{}", peer_port))
+      .collect()
+  }
+}
+
+impl Default for NodeConfig {
+  fn default() -> Self {
+    Self {
+      network: Network::default(),
+      listen: None,
+      data_dir: None,
+      seed_peers: vec![],
+      miner_address: None,
+      miner_password: None,
+      miner_unencrypted: false,
+      log_level: "info".to_string(),
+      genesis: None,
+      peer_sync_secs: 20,
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::path::PathBuf;
+
+  use blockchain::core::{network::Network, wallet::Wallet};
+
+  use super::{ConfigError, NodeArgs, NodeConfig};
+
+  #[test]
+  fn test_flags_override_file() {
+    let config: NodeConfig = toml::from_str(r#"
+      network = "testnet"
+      listen = "127.0.0.1:18005"
+      seed_peers = ["10.0.0.2:18000"]
+      log_level = "debug"
+    "#).unwrap();
+
+    assert_eq!(config.data_dir(), PathBuf::from("data/testnet/node-18005"));
+    assert_eq!(config.peers(), vec!["10.0.0.2:18000".to_string()]);
+    assert_eq!(NodeConfig::default().peers(), vec!["127.0.0.1:8001", "127.0.0.1:8002", "127.0.0.1:8003"]);
+
+    let path = std::env::temp_dir().join(format!("node-config-{}.toml", std::process::id()));
+    std::fs::write(&path, toml::to_string(&config).unwrap()).unwrap();
+
+    let miner_address = Wallet::new(Network::Testnet).address();
+    let args = NodeArgs {
+      config: Some(path.clone()),
+      listen: Some("0.0.0.0:18009".parse().unwrap()),
+      miner_address: Some(miner_address.clone()),
+      ..NodeArgs::default()
+    };
+    let loaded = NodeConfig::load(args).unwrap();
+
+    assert_eq!(loaded.network, Network::Testnet);
+    assert_eq!(loaded.listen().port(), 18009);
+    assert_eq!(loaded.log_level, "debug");
+    assert_eq!(loaded.miner_address, Some(miner_address.clone()));
+
+    let args = NodeArgs { config: Some(path.clone()), network: Some(Network::Mainnet), miner_address: Some(miner_address), ..NodeArgs::default() };
+    assert!(matches!(NodeConfig::load(args), Err(ConfigError::MinerAddress(_))));
+
+    std::fs::remove_file(path).unwrap();
+
+    assert_eq!(NodeConfig::from_file("node.example.toml").unwrap().listen().port(), 8000);
+  }
+}
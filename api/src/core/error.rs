@@ -0,0 +1,84 @@
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use blockchain::core::error::{BlockchainError, KeystoreError, ReceiveBlockError, TransactionError};
+use serde::Serialize;
+
+/// JSON body of every error response, `error` is a stable machine readable kind.
+#[derive(Serialize, Debug)]
+pub struct ErrorResponseDTO {
+  error: &'static str,
+  message: String,
+}
+
+/// Error of the blockchain crate together with the HTTP status it maps to.
+#[derive(Debug)]
+pub struct ApiError(BlockchainError);
+
+impl ApiError {
+  fn kind(&self) -> (StatusCode, &'static str) {
+    match &self.0 {
+      BlockchainError::Transaction(err) => match err {
+        TransactionError::InvalidSignature => (StatusCode::FORBIDDEN, "invalid_signature"),
+        TransactionError::InvalidEncoding(_) => (StatusCode::BAD_REQUEST, "malformed_encoding"),
+        TransactionError::InvalidKey(_) => (StatusCode::BAD_REQUEST, "invalid_key"),
+        TransactionError::InvalidAddress(_) => (StatusCode::BAD_REQUEST, "invalid_address"),
+        TransactionError::SenderMismatch => (StatusCode::FORBIDDEN, "sender_mismatch"),
+        TransactionError::Duplicate => (StatusCode::CONFLICT, "duplicate_transaction"),
+        _ => (StatusCode::UNPROCESSABLE_ENTITY, "transaction_rejected"),
+      },
+      BlockchainError::Key(_) => (StatusCode::BAD_REQUEST, "invalid_key"),
+      BlockchainError::Decode(_) => (StatusCode::BAD_REQUEST, "malformed_encoding"),
+      BlockchainError::Address(_) => (StatusCode::BAD_REQUEST, "invalid_address"),
+      BlockchainError::Amount(_) => (StatusCode::BAD_REQUEST, "invalid_amount"),
+      BlockchainError::Keystore(KeystoreError::InvalidPassword) => (StatusCode::UNAUTHORIZED, "invalid_password"),
+      BlockchainError::Keystore(_) => (StatusCode::BAD_REQUEST, "invalid_keystore"),
+      BlockchainError::Chain(_) => (StatusCode::UNPROCESSABLE_ENTITY, "chain_invalid"),
+      BlockchainError::Block(ReceiveBlockError::Rejected(_)) => (StatusCode::BAD_REQUEST, "block_rejected"),
+      BlockchainError::Block(ReceiveBlockError::Invalid(_)) => (StatusCode::UNPROCESSABLE_ENTITY, "chain_invalid"),
+      BlockchainError::Block(ReceiveBlockError::Storage(_)) | BlockchainError::Storage(_) => {
+        (StatusCode::INTERNAL_SERVER_ERROR, "storage")
+      }
+    }
+  }
+}
+
+impl std::fmt::Display for ApiError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.0)
+  }
+}
+
+impl ResponseError for ApiError {
+  fn status_code(&self) -> StatusCode {
+    self.kind().0
+  }
+
+  fn error_response(&self) -> HttpResponse {
+    let (status, error) = self.kind();
+
+    HttpResponse::build(status)
+      .json(ErrorResponseDTO { error, message: self.0.to_string() })
+  }
+}
+
+impl<E: Into<BlockchainError>> From<E> for ApiError {
+  fn from(err: E) -> Self {
+    Self(err.into())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use actix_web::{http::StatusCode, ResponseError};
+  use blockchain::core::error::{AddressError, KeyError, TransactionError};
+
+  use super::ApiError;
+
+  #[test]
+  fn test_errors_map_to_distinct_statuses() {
+    assert_eq!(ApiError::from(TransactionError::InvalidSignature).status_code(), StatusCode::FORBIDDEN);
+    assert_eq!(ApiError::from(KeyError::InvalidHex).status_code(), StatusCode::BAD_REQUEST);
+    assert_eq!(ApiError::from(AddressError::ChecksumMismatch).status_code(), StatusCode::BAD_REQUEST);
+    assert_eq!(ApiError::from(TransactionError::Duplicate).status_code(), StatusCode::CONFLICT);
+    assert_eq!(ApiError::from(TransactionError::SelfTransfer).status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+  }
+}
@@ -1,109 +1,212 @@
-use actix_web::{middleware, web, App, HttpResponse, HttpServer};
-use blockchain::core::{blockchain::{Blockchain, BlocksChain}, peer::PingResponse, transaction::Transaction, wallet::Wallet};
+use actix_web::{middleware, web, App, HttpResponse, HttpServer, ResponseError};
+use blockchain::{core::{
+  amount::Amount,
+  block::{Block, BlockHeader, BlockSearch, BlockSearchResult},
+  blockchain::{Blockchain, BlocksChain},
+  error::{BlockchainError, DecodeError, TransactionError},
+  genesis::GenesisSpec,
+  network::Network,
+  peer::PingResponse,
+  raw_transaction::RawTransaction,
+  transaction::Transaction,
+  utxo::{select_coins, LedgerMode, OutPoint, TxOutput, UtxoTransaction},
+  wallet::{hd::HdWallet, Wallet, WalletDetails},
+}, utils::{address::{self, AddressFormat}, merkle::MerkleProof, serializable::Serializable}};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use std::{io::Read, sync::{Arc, Mutex}, thread, time::Duration};
-use log::{debug, info};
+use log::{debug, info, warn};
 use std::collections::HashMap;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::BufReader;
+use std::path::Path;
+
+use super::{config::{ConfigError, NodeConfig}, error::ApiError};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TransactionsResponseDTO {
   transaction_count: usize,
   transactions: Vec<Transaction>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  utxo_transactions: Vec<UtxoTransaction>,
+}
+
+#[derive(Serialize)]
+struct UnspentOutputDTO {
+  out_point: OutPoint,
+  output: TxOutput,
+}
+
+/// Everything a light client needs to check that `transaction` is part of
+/// the block hashing to `block_hash`.
+#[derive(Serialize)]
+struct InclusionProofDTO {
+  block_hash: String,
+  header: BlockHeader,
+  transaction: String,
+  proof: MerkleProof,
 }
 
 #[derive(Serialize)]
 struct AddressAmountResponseDTO {
-  amount: f64,
+  amount: Amount,
 }
 
 #[derive(Debug, Clone)]
 pub struct ApiServer {
-  port: u16,
+  config: NodeConfig,
   cache: Arc<Mutex<HashMap<String, Arc<Mutex<Blockchain>>>>>,
   neighbors: Arc<Mutex<Vec<String>>>,
   candidates: Arc<Mutex<Vec<String>>>,
 }
 
+/// A new HD wallet: the phrase to back up and its first receiving address.
+#[derive(Serialize)]
+struct HdWalletDetailsDTO {
+  mnemonic: String,
+  #[serde(flatten)]
+  wallet: WalletDetails,
+}
+
+/// Transaction signed by the client, either as JSON or as the hex encoded
+/// binary record stored in blocks.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum SignedTransactionReqDTO {
+  Signed(Transaction),
+  Utxo(UtxoTransaction),
+  Raw { raw: String },
+}
+
+/// Client signed transaction of either ledger mode, gossiped as plain JSON.
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+enum SignedTransaction {
+  Account(Transaction),
+  Utxo(UtxoTransaction),
+}
+
+/// Payout address of this node and what it has earned so far.
+#[derive(Serialize)]
+struct MinerResponseDTO {
+  address: String,
+  bech32_address: String,
+  network: Network,
+  balance: Amount,
+}
+
+/// What a client needs to sign the next transaction of `address`.
+#[derive(Serialize)]
+struct NonceResponseDTO {
+  address: String,
+  nonce: u64,
+  chain_id: String,
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct TransactionReqDTO {
   private_key: String,
   public_key: String,
   blockchain_address: String,
   recipient_address: String,
-  amount: String,
+  amount: Amount,
 }
 
 impl ApiServer {
-  const BLOCKCHAIN_PORT_RANGE: (u16, u16) = (8000, 8003); // start, end
-  const NEIGHBOR_IP_RANGE: (u8, u8) = (0, 1); // start, end
-  const NEIGHBOR_IP_SYNC_TIME: u64 = 20;
-
-  pub fn get_neighbors(&self) -> () {
-    let ip_string = format!("{}:{}", "127.0.0.1", self.port);
-    let ip: &str = ip_string.as_str();
-    let ipv4_regex = regex::Regex::new(
-      r"^(\d{1,3}(?:\.\d{1,3}){3}):(\d+)$"
-    ).unwrap();
-
-    let ips = ipv4_regex
-      .captures(ip)
-      .unwrap()
-      .iter()
-      .map(|matched_item| matched_item.unwrap().as_str().to_string())
-      .collect::<Vec<String>>();
-
-    let extracted_ip = ips.get(1).unwrap();
-
-    let current_ip = extracted_ip.clone();
-    let ip_parts: Vec<&str> = current_ip.split('.').collect();
-    let last_ip_part = ip_parts.last().unwrap().parse::<u8>().unwrap();
+  const MINER_KEYSTORE: &'static str = "miner.json";
 
+  pub fn get_neighbors(&self) {
     let mut neighbors = self.candidates.lock().unwrap();
 
-    let port_range_start = Self::BLOCKCHAIN_PORT_RANGE.0;
-    let port_range_end = Self::BLOCKCHAIN_PORT_RANGE.1;
-    let ip_range_start = Self::NEIGHBOR_IP_RANGE.0;
-    let ip_range_end = Self::NEIGHBOR_IP_RANGE.1;
-
-    for port in port_range_start..=port_range_end {
-      for guest_ip in ip_range_start..=ip_range_end {
-        let guest_host = format!("{}.{}", ip_parts[0..3].join("."), last_ip_part + guest_ip);
-        let guest_target = format!("{}:{}", guest_host, port);
-
-        if guest_target != current_ip {
-          neighbors.push(guest_target);
-        }
-      }
-    }
+    neighbors.extend(self.config.peers());
 
     info!("find neighbors: {:?}", neighbors);
   }
 
-  pub fn new(port: u16) -> Self {
+  pub fn new(config: NodeConfig) -> Result<Self, ConfigError> {
     let cache = Arc::new(Mutex::new(HashMap::new()));
     let neighbors = Arc::new(Mutex::new(vec![]));
     let candidates = Arc::new(Mutex::new(vec![]));
   
     let api_server  = Self {
-      port,
+      config,
       cache,
       neighbors,
       candidates
     };
 
-    let blockchain_miner_wallet = Wallet::new();
+    let network = api_server.config.network;
+
+    let data_dir = api_server.config.data_dir();
+
+    let miner_address = Self::load_miner_address(&api_server.config)
+      .map_err(|err| ConfigError::Miner { path: data_dir.clone(), source: Box::new(err) })?;
+
+    let genesis = Self::load_genesis(api_server.config.genesis.as_deref(), network)?;
 
-    let blockchain = Blockchain::new(blockchain_miner_wallet.address());
+    let blockchain = Blockchain::open(miner_address, genesis, &data_dir)
+      .map_err(|source| ConfigError::Storage { path: data_dir.clone(), source })?;
 
     {
       let lock = api_server.cache.lock();
       lock.unwrap().insert("blockchain".to_string(), Arc::new(Mutex::new(blockchain)));
     }
 
-    api_server
+    Ok(api_server)
+  }
+
+  /// The configured payout address, or the address of the miner wallet kept in
+  /// the data directory, which is created on first start.
+  fn load_miner_address(config: &NodeConfig) -> Result<String, ConfigError> {
+    if let Some(miner_address) = config.miner_address.as_ref() {
+      return Ok(miner_address.clone());
+    }
+
+    let path = config.data_dir().join(Self::MINER_KEYSTORE);
+    let password = config.miner_password.as_deref().unwrap_or_default();
+
+    if path.exists() {
+      let wallet = Wallet::import_keystore(&path, password)?;
+      address::validate_for(&wallet.address(), config.network)?;
+
+      info!("loaded miner wallet {} from {}", wallet.address(), path.display());
+
+      return Ok(wallet.address());
+    }
+
+    if config.miner_password.is_none() && !config.miner_unencrypted {
+      return Err(ConfigError::MissingMinerPassword);
+    }
+
+    let wallet = Wallet::new(config.network);
+
+    fs::create_dir_all(config.data_dir()).map_err(BlockchainError::from)?;
+    wallet.export_keystore(&path, password)?;
+
+    if password.is_empty() {
+      warn!("miner wallet {} written to {} without a password", wallet.address(), path.display());
+    } else {
+      info!("miner wallet {} written to {}", wallet.address(), path.display());
+    }
+
+    Ok(wallet.address())
+  }
+
+  /// The genesis spec named by the config, the built in genesis of `network`
+  /// when none is configured.
+  fn load_genesis(path: Option<&Path>, network: Network) -> Result<GenesisSpec, ConfigError> {
+    let Some(path) = path else {
+      return Ok(network.genesis());
+    };
+
+    let genesis = GenesisSpec::from_file(path)
+      .map_err(|source| ConfigError::Genesis { path: path.to_path_buf(), source })?;
+
+    if genesis.network != network {
+      return Err(ConfigError::GenesisNetwork { path: path.to_path_buf(), expected: network, found: genesis.network });
+    }
+
+    Ok(genesis)
   }
 
   async fn handle_ping() -> HttpResponse {
@@ -155,6 +258,11 @@ impl ApiServer {
     let address = path.into_inner();
 
     let api_server = data.get_ref();
+
+    if let Err(err) = address::validate_for(&address, api_server.config.network) {
+      return ApiError::from(err).error_response();
+    }
+
     let cache = api_server.cache.lock().unwrap();
     let blockchain = cache.get(&"blockchain".to_string()).unwrap().lock().unwrap();
 
@@ -168,23 +276,47 @@ impl ApiServer {
       .json(response)
   }
 
+  async fn get_miner_handler(data: web::Data<Arc<Self>>) -> HttpResponse {
+    let api_server = data.get_ref();
+
+    let response = Self::with_blockchain(api_server, |blockchain| {
+      let address = blockchain.miner_address().to_string();
+
+      MinerResponseDTO {
+        bech32_address: address::convert(&address, AddressFormat::Bech32).unwrap_or_else(|_| address.clone()),
+        balance: blockchain.calculate_reward(address.clone()),
+        network: blockchain.network(),
+        address,
+      }
+    });
+
+    HttpResponse::Ok()
+      .json(response)
+  }
+
   // mine handler
   async fn mine_handler(data: web::Data<Arc<Self>>) -> HttpResponse {
     let api_server = data.get_ref();
 
-    let mut cache = api_server.cache.lock().unwrap();
-    let mut blockchain = cache.get_mut(&"blockchain".to_string()).unwrap().lock().unwrap();
+    let mined = {
+      let mut cache = api_server.cache.lock().unwrap();
+      let mut blockchain = cache.get_mut(&"blockchain".to_string()).unwrap().lock().unwrap();
 
-    let is_mined = blockchain.mine();
+      blockchain.mine()
+    };
 
-    if !is_mined {
-      return HttpResponse::InternalServerError()
-        .json("Something went wrong");
+    if let Err(err) = mined {
+      info!("mining failed: {}", err);
+
+      return ApiError::from(err).error_response();
     }
 
     // notify peers to remove the trxs in their pool
     let _ = Self::remove_mined_transactions_from_neighbors_transactions_pool(api_server).await;
 
+    // hand the new block to peers so they do not need to download the whole chain
+    let _ = Self::broadcast_last_block(api_server).await;
+
     // consensus
     let _ = Self::build_consensus(api_server).await;
 
@@ -192,8 +324,54 @@ impl ApiServer {
       .json("Everything has gone through")
   } 
 
+  async fn broadcast_last_block(api_server: &Self) -> Result<(), reqwest::Error> {
+    let block = Self::with_blockchain(api_server, |blockchain| blockchain.last_block().cloned());
+    let neighbors = api_server.neighbors.lock().unwrap().clone();
+
+    let client = reqwest::Client::builder()
+      .timeout(Duration::from_secs(5))
+      .no_proxy()
+      .build()?;
+
+    for neighbor in neighbors.iter() {
+      let url = format!("http://{}/block", neighbor);
+
+      // an unreachable neighbor must not keep the block from the others
+      if let Err(err) = client.post(url).json(&block).send().await {
+        warn!("failed to announce block to neighbor {}: {}", neighbor, err);
+      }
+    }
+
+    Ok(())
+  }
+
+  async fn handle_block(data: web::Data<Arc<Self>>, block: web::Json<Block>) -> HttpResponse {
+    let result = Self::with_blockchain(data.get_ref(), |blockchain| blockchain.receive_block(block.into_inner()));
+
+    match result {
+      Ok(acceptance) => {
+        info!("received block: {:?}", acceptance);
+
+        HttpResponse::Ok()
+          .json(acceptance)
+      }
+      Err(err) => {
+        info!("received block refused: {}", err);
+
+        ApiError::from(err).error_response()
+      }
+    }
+  }
+
+  async fn handle_tips(data: web::Data<Arc<Self>>) -> HttpResponse {
+    let tips = Self::with_blockchain(data.get_ref(), |blockchain| blockchain.tips());
+
+    HttpResponse::Ok()
+      .json(tips)
+  }
+
   async fn build_consensus(api_server: &Self) -> Result<(), reqwest::Error> {
-    let neighbors = api_server.neighbors.lock().unwrap();
+    let neighbors = api_server.neighbors.lock().unwrap().clone();
 
     let client = reqwest::Client::builder()
       .timeout(Duration::from_secs(5))
@@ -231,30 +409,27 @@ impl ApiServer {
 
     if let Ok(replaced) = result {
       if replaced {
-        info!("Blockchain replaced by consensus from server with port {}", api_server.port);
+        info!("Blockchain replaced by consensus from server {}", api_server.config.listen());
       } else {
-        info!("Blockchain not replaced for server with {}", api_server.port);
+        info!("Blockchain not replaced for server with {}", api_server.config.listen());
       }
     }
 
     if let Err(err) = result {
-      info!("Server with port {} consensus failed with error {:?}", api_server.port, err);
+      info!("Server {} consensus failed with error {:?}", api_server.config.listen(), err);
     }
   }
 
   async fn resolve_conflict(api_server: &Self) -> Result<bool, reqwest::Error> {
-    info!("Attempting to resolve conflict with server of port {}", api_server.port);
+    info!("Attempting to resolve conflict with server {}", api_server.config.listen());
 
     let client = reqwest::Client::builder()
       .timeout(Duration::from_secs(5))
       .no_proxy()
       .build()?;
 
-    let neighbors = api_server.neighbors.lock().unwrap();
-    let mut cache = api_server.cache.lock().unwrap();
-    let mut blockchain = cache.get_mut(&"blockchain".to_string()).unwrap().lock().unwrap();
+    let neighbors = api_server.neighbors.lock().unwrap().clone();
     let mut chain_modified = false;
-    let mut max_length = blockchain.chain.len();
 
     info!("chain conflict resolution begins");
 
@@ -264,29 +439,60 @@ impl ApiServer {
       let random_secs: u64 = rng.random_range(0..=5);
       thread::sleep(Duration::from_secs(random_secs));
 
-      info!("request chain from neighbor {} with port {}", neighbor, api_server.port);
+      info!("request chain from neighbor {} on server {}", neighbor, api_server.config.listen());
 
       let url = format!("http://{}/chain", neighbor);
 
       let neighbor_chain_req_response = client.get(url).send().await?;
       let neighbor_chain: BlocksChain = neighbor_chain_req_response.json().await?;
-      let neighbor_chain_len = neighbor_chain.len();
-      let neighbor_chain_contains_more_blocks = neighbor_chain_len > max_length;
-      let neighbor_chain_is_valid = Blockchain::chain_is_valid(&neighbor_chain);
-      let should_replace_own_chain = neighbor_chain_contains_more_blocks && neighbor_chain_is_valid;
 
-      if should_replace_own_chain {
-        max_length = neighbor_chain_len;
-        blockchain.chain = neighbor_chain.clone();
-        chain_modified = true;
+      // compared and replaced under one lock, so a block added in between is
+      // never overwritten by a chain that was only heavier than the old tip
+      let replaced = Self::with_blockchain(api_server, |blockchain| {
+        if !blockchain.shares_genesis(&neighbor_chain) {
+          info!("ignoring chain of neighbor {} built on a different genesis block", neighbor);
+
+          return None;
+        }
+
+        if !blockchain.is_heavier_chain(&neighbor_chain) {
+          return None;
+        }
+
+        if let Err(err) = blockchain.validate_chain(&neighbor_chain) {
+          info!("ignoring chain of neighbor {}: {}", neighbor, err);
+
+          return None;
+        }
+
+        Some(blockchain.replace_chain(neighbor_chain))
+      });
+
+      match replaced {
+        None => continue,
+        Some(Ok(event)) => info!("{}", event),
+        Some(Err(err)) => {
+          info!("storing chain of neighbor {} failed with error {}", neighbor, err);
 
-        info!("chain of server with port {} have been replaced with that of neighbor {}", api_server.port, neighbor);
+          continue;
+        }
       }
+
+      chain_modified = true;
+
+      info!("chain of server {} have been replaced with that of neighbor {}", api_server.config.listen(), neighbor);
     }
 
     Ok(chain_modified)
   }
 
+  fn with_blockchain<T>(api_server: &Self, f: impl FnOnce(&mut Blockchain) -> T) -> T {
+    let mut cache = api_server.cache.lock().unwrap();
+    let mut blockchain = cache.get_mut(&"blockchain".to_string()).unwrap().lock().unwrap();
+
+    f(&mut blockchain)
+  }
+
   async fn handle_chain_retrieval(data: web::Data<Arc<Self>>) -> HttpResponse {
     let api_server = data.get_ref();
     let cache = api_server.cache.lock().unwrap();
@@ -296,6 +502,29 @@ impl ApiServer {
       .json(blockchain.chain.clone())
   }
 
+  async fn handle_inclusion_proof(data: web::Data<Arc<Self>>, path: web::Path<(usize, usize)>) -> HttpResponse {
+    let (height, index) = path.into_inner();
+
+    let response = Self::with_blockchain(data.get_ref(), |blockchain| {
+      let block = match blockchain.search_block(BlockSearch::SearchByIndex(height)) {
+        BlockSearchResult::Success(block) => block,
+        _ => return None,
+      };
+
+      Some(InclusionProofDTO {
+        block_hash: hex::encode(block.hash()),
+        header: block.header.clone(),
+        transaction: hex::encode(block.transactions.get(index)?),
+        proof: block.merkle_proof(index)?,
+      })
+    });
+
+    match response {
+      Some(response) => HttpResponse::Ok().json(response),
+      None => HttpResponse::NotFound().json("no such block or transaction"),
+    }
+  }
+
   /**
    * remove mind transactions from neighbors transactions pool
    */
@@ -305,14 +534,14 @@ impl ApiServer {
       .no_proxy()
       .build()?;
   
-    let neighbors = api_server.neighbors.lock().unwrap();
+    let neighbors = api_server.neighbors.lock().unwrap().clone();
 
     for neighbor in neighbors.iter() {
       let url = format!("http://{}/clear_transactions_from_pool", neighbor);
 
       let result = client.delete(url).send().await?;
 
-      println!("neighbor {} trx removal response {:?}", neighbor, result);
+      debug!("neighbor {} trx removal response {:?}", neighbor, result);
     }
 
     Ok(())
@@ -360,10 +589,15 @@ impl ApiServer {
   }
 
   async fn get_wallet_details(&self) -> HttpResponse {
-    let new_wallet = Wallet::new();
+    let hd_wallet = HdWallet::generate(self.config.network);
+
+    let response = HdWalletDetailsDTO {
+      mnemonic: hd_wallet.mnemonic(),
+      wallet: hd_wallet.wallet(0).expect("index 0 is not hardened").get_details(),
+    };
 
     HttpResponse::Ok()
-      .json(new_wallet.get_details())
+      .json(response)
   }
 
   async fn transact_handler(transaction: web::Json<TransactionReqDTO>, data: web::Data<Arc<Self>>) -> HttpResponse {
@@ -371,34 +605,145 @@ impl ApiServer {
 
     debug!("receive json info: {:?}", trx_dto);
 
-    let trx_amount = trx_dto.amount.parse::<f64>();
+    let api_server = data.get_ref();
 
-    let wallet = Wallet::new_from(
-      &trx_dto.public_key,
-      &trx_dto.private_key,
-      &trx_dto.blockchain_address,
-    );
+    for address in [&trx_dto.blockchain_address, &trx_dto.recipient_address] {
+      if let Err(err) = address::validate_for(address, api_server.config.network) {
+        return ApiError::from(err).error_response();
+      }
+    }
 
-    let api_server = data.get_ref();
+    let wallet = match Wallet::new_from(&trx_dto.public_key, &trx_dto.private_key, &trx_dto.blockchain_address) {
+      Ok(wallet) => wallet,
+      Err(err) => return ApiError::from(err).error_response(),
+    };
 
-    let mut cache = api_server.cache.lock().unwrap();
-    let mut blockchain = cache.get_mut(&"blockchain".to_string()).unwrap().lock().unwrap();
+    if Self::with_blockchain(api_server, |blockchain| blockchain.ledger()) == LedgerMode::Utxo {
+      return Self::transact_utxo(api_server, &wallet, &trx_dto).await;
+    }
 
-    let wallet_trx = wallet.sign_transaction(trx_dto.recipient_address.clone(), trx_amount.unwrap());
+    // sign while holding the lock so the nonce cannot be taken by another request
+    let add_result = Self::with_blockchain(api_server, |blockchain| {
+      let nonce = blockchain.next_nonce(&wallet.address());
+      let wallet_trx = wallet.sign_transaction(trx_dto.recipient_address.clone(), trx_dto.amount, nonce, blockchain.chain_id());
 
-    let add_result = blockchain.add_transaction(&wallet_trx);
+      blockchain.add_transaction(&wallet_trx).map(|_| wallet_trx)
+    });
 
-    if !add_result {
-      info!("adding transaction to blockchain failed");
+    let wallet_trx = match add_result {
+      Ok(wallet_trx) => wallet_trx,
+      Err(err) => {
+        info!("adding transaction to blockchain failed: {}", err);
 
-      return HttpResponse::BadRequest()
-        .json("something just didn't add up. We're checking though");
-    }
+        return ApiError::from(err).error_response();
+      }
+    };
 
     info!("add transaction to blockchain okay");
 
     // sync transaction with neighbors
-    let result = Self::sync_transaction_with_neighbors(api_server, &wallet_trx).await;
+    let result = Self::sync_transaction_with_neighbors(api_server, "sync_transaction", &wallet_trx).await;
+
+    info!("sync final result: {:?}", result);
+
+    HttpResponse::Ok()
+      .json("add transaction to blockchain ok")
+  }
+
+  /// Pool a transaction signed by the client and gossip it, the node never
+  /// sees the private key.
+  async fn submit_transaction_handler(data: web::Data<Arc<Self>>, request: web::Json<SignedTransactionReqDTO>) -> HttpResponse {
+    let transaction = match request.into_inner() {
+      SignedTransactionReqDTO::Signed(transaction) => SignedTransaction::Account(transaction),
+      SignedTransactionReqDTO::Utxo(utxo_trx) => SignedTransaction::Utxo(utxo_trx),
+      SignedTransactionReqDTO::Raw { raw } => match Self::decode_raw_transaction(&raw) {
+        Ok(transaction) => transaction,
+        Err(err) => return ApiError::from(err).error_response(),
+      },
+    };
+
+    let api_server = data.get_ref();
+
+    let (add_result, route) = Self::with_blockchain(api_server, |blockchain| match &transaction {
+      SignedTransaction::Account(transaction) => (blockchain.add_transaction(transaction), "sync_transaction"),
+      SignedTransaction::Utxo(utxo_trx) => (blockchain.add_utxo_transaction(utxo_trx), "sync_utxo_transaction"),
+    });
+
+    if let Err(err) = add_result {
+      info!("adding signed transaction to blockchain failed: {}", err);
+
+      return ApiError::from(err).error_response();
+    }
+
+    let result = Self::sync_transaction_with_neighbors(api_server, route, &transaction).await;
+
+    info!("sync final result: {:?}", result);
+
+    HttpResponse::Ok()
+      .json("add transaction to blockchain ok")
+  }
+
+  /// Decode the binary record of either ledger mode, told apart by its version byte.
+  fn decode_raw_transaction(raw: &str) -> Result<SignedTransaction, DecodeError> {
+    let bytes = hex::decode(raw).map_err(|_| DecodeError::InvalidField("raw"))?;
+
+    if bytes.first() == Some(&<UtxoTransaction as Serializable<_>>::VERSION) {
+      return Ok(SignedTransaction::Utxo(<UtxoTransaction as Serializable<_>>::deserialize(&bytes)?));
+    }
+
+    Ok(SignedTransaction::Account(RawTransaction::deserialize(&bytes)?.to_transaction()?))
+  }
+
+  async fn get_nonce_handler(data: web::Data<Arc<Self>>, path: web::Path<String>) -> HttpResponse {
+    let address = path.into_inner();
+    let api_server = data.get_ref();
+
+    if let Err(err) = address::validate_for(&address, api_server.config.network) {
+      return ApiError::from(err).error_response();
+    }
+
+    let response = Self::with_blockchain(api_server, |blockchain| NonceResponseDTO {
+      nonce: blockchain.next_nonce(&address),
+      chain_id: blockchain.chain_id().to_string(),
+      address,
+    });
+
+    HttpResponse::Ok()
+      .json(response)
+  }
+
+  /// Spend the wallet's unspent outputs, sending the change back to it.
+  async fn transact_utxo(api_server: &Self, wallet: &Wallet, trx_dto: &TransactionReqDTO) -> HttpResponse {
+    let add_result = Self::with_blockchain(api_server, |blockchain| {
+      let unspent = blockchain.unspent_outputs(&wallet.address());
+
+      let (inputs, change) = select_coins(&unspent, trx_dto.amount).ok_or_else(|| TransactionError::InsufficientBalance {
+        address: wallet.address(),
+        available: blockchain.spendable_balance(&wallet.address()),
+        requested: trx_dto.amount,
+      })?;
+
+      let mut outputs = vec![TxOutput { address: trx_dto.recipient_address.clone(), amount: trx_dto.amount }];
+
+      if !change.is_zero() {
+        outputs.push(TxOutput { address: wallet.address(), amount: change });
+      }
+
+      let utxo_trx = wallet.sign_utxo_transaction(inputs, outputs, blockchain.chain_id());
+
+      blockchain.add_utxo_transaction(&utxo_trx).map(|_| utxo_trx)
+    });
+
+    let utxo_trx = match add_result {
+      Ok(utxo_trx) => utxo_trx,
+      Err(err) => {
+        info!("adding utxo transaction to blockchain failed: {}", err);
+
+        return ApiError::from(err).error_response();
+      }
+    };
+
+    let result = Self::sync_transaction_with_neighbors(api_server, "sync_utxo_transaction", &utxo_trx).await;
 
     info!("sync final result: {:?}", result);
 
@@ -406,10 +751,10 @@ impl ApiServer {
       .json("add transaction to blockchain ok")
   }
 
-  pub async fn sync_transaction_with_neighbors(api_server: &Self, trx: &Transaction) -> Result<(), reqwest::Error> {
+  pub async fn sync_transaction_with_neighbors<T: Serialize>(api_server: &Self, route: &str, trx: &T) -> Result<(), reqwest::Error> {
     info!("begin transaction sync with neighbors");
 
-    let neighbors = api_server.neighbors.lock().unwrap();
+    let neighbors = api_server.neighbors.lock().unwrap().clone();
 
     let reqwest_client = reqwest::Client::builder()
       .timeout(Duration::from_secs(5))
@@ -419,14 +764,12 @@ impl ApiServer {
     info!("Total neighbors: {}", neighbors.len());
 
     for neighbor in neighbors.iter() {
-      let url = format!("http://{}/sync_transaction", neighbor);
+      let url = format!("http://{}/{}", neighbor, route);
 
-      let response = reqwest_client.post(url)
-        .json(trx)
-        .send()
-        .await?;
-
-      info!("Sync trx with neighbor {} and result is {:?}", neighbor, response);
+      match reqwest_client.post(url).json(trx).send().await {
+        Ok(response) => info!("Sync trx with neighbor {} and result is {:?}", neighbor, response),
+        Err(err) => warn!("failed to sync trx with neighbor {}: {}", neighbor, err),
+      }
     }
 
     Ok(())
@@ -442,11 +785,10 @@ impl ApiServer {
 
     let add_result = blockchain.add_transaction(&wallet_trx);
 
-    if !add_result {
-      info!("syncing transaction to blockchain failed");
+    if let Err(err) = add_result {
+      info!("syncing transaction to blockchain failed: {}", err);
 
-      return HttpResponse::BadRequest()
-        .json("something just didn't add up. We're checking though");
+      return ApiError::from(err).error_response();
     }
 
     info!("syncing transaction to blockchain okay");
@@ -455,6 +797,40 @@ impl ApiServer {
       .json("syncing transaction to blockchain ok")
   }
 
+  pub async fn handle_utxo_transaction_sync(data: web::Data<Arc<Self>>, transaction: web::Json<UtxoTransaction>) -> HttpResponse {
+    let utxo_trx = transaction.into_inner();
+
+    let add_result = Self::with_blockchain(data.get_ref(), |blockchain| blockchain.add_utxo_transaction(&utxo_trx));
+
+    if let Err(err) = add_result {
+      info!("syncing utxo transaction to blockchain failed: {}", err);
+
+      return ApiError::from(err).error_response();
+    }
+
+    HttpResponse::Ok()
+      .json("syncing transaction to blockchain ok")
+  }
+
+  pub async fn get_unspent_outputs_handler(data: web::Data<Arc<Self>>, path: web::Path<String>) -> HttpResponse {
+    let address = path.into_inner();
+    let api_server = data.get_ref();
+
+    if let Err(err) = address::validate_for(&address, api_server.config.network) {
+      return ApiError::from(err).error_response();
+    }
+
+    let unspent = Self::with_blockchain(api_server, |blockchain| blockchain.unspent_outputs(&address));
+
+    let response: Vec<UnspentOutputDTO> = unspent
+      .into_iter()
+      .map(|(out_point, output)| UnspentOutputDTO { out_point, output })
+      .collect();
+
+    HttpResponse::Ok()
+      .json(response)
+  }
+
   pub async fn list_transactions(data: web::Data<Arc<Self>>) -> HttpResponse {
     let api_server = data.get_ref();
 
@@ -464,10 +840,12 @@ impl ApiServer {
     let mut transactions_result = TransactionsResponseDTO {
       transaction_count: 0,
       transactions: Vec::<Transaction>::new(),
+      utxo_transactions: Vec::<UtxoTransaction>::new(),
     };
 
     transactions_result.transactions = blockchain.get_transactions();
-    transactions_result.transaction_count = transactions_result.transactions.len();
+    transactions_result.utxo_transactions = blockchain.get_utxo_transactions();
+    transactions_result.transaction_count = transactions_result.transactions.len() + transactions_result.utxo_transactions.len();
 
     debug!("trxs {:?}", transactions_result);
 
@@ -489,19 +867,27 @@ impl ApiServer {
         .route("/wallet_details", web::get().to(Self::get_wallet_details_handler))
         .route("/transact", web::post().to(Self::transact_handler))
         .route("/transactions", web::get().to(Self::list_transactions))
+        .route("/transactions", web::post().to(Self::submit_transaction_handler))
+        .route("/nonce/{address}", web::get().to(Self::get_nonce_handler))
         .route("/mine", web::get().to(Self::mine_handler))
+        .route("/miner", web::get().to(Self::get_miner_handler))
         .route("/amount/{address}", web::get().to(Self::get_amount_handler))
         .route("/ping", web::get().to(Self::handle_ping))
         .route("/sync_transaction", web::post().to(Self::handle_transactions_sync))
+        .route("/sync_utxo_transaction", web::post().to(Self::handle_utxo_transaction_sync))
+        .route("/utxos/{address}", web::get().to(Self::get_unspent_outputs_handler))
         .route("/clear_transactions_from_pool", web::delete().to(Self::handle_transactions_pool_reset))
         .route("/consensus", web::get().to(Self::handle_consensus))
         .route("/chain", web::get().to(Self::handle_chain_retrieval))
+        .route("/block", web::post().to(Self::handle_block))
+        .route("/tips", web::get().to(Self::handle_tips))
+        .route("/blocks/{height}/proof/{index}", web::get().to(Self::handle_inclusion_proof))
       });
 
-    println!("Server running on port: {}", self.port);
+    info!("Server running on: {}", self.config.listen());
 
     server
-      .bind(("0.0.0.0", self.port))
+      .bind(self.config.listen())
       .unwrap()
       .run()
       .await
@@ -521,7 +907,7 @@ impl ApiServer {
 
         api_clone.register_neighbors();
 
-        thread::sleep(Duration::from_secs(Self::NEIGHBOR_IP_SYNC_TIME));
+        thread::sleep(Duration::from_secs(api_clone.config.peer_sync_secs));
       }
     });
   } 
@@ -536,7 +922,7 @@ impl ApiServer {
       let neighbors_contain_candidate = neighbors.iter().any(|s| s == candidate);
 
       if neighbors_contain_candidate {
-        info!("candidate: {} already synced by server with port: {}", candidate, self.port);
+        info!("candidate: {} already synced by server: {}", candidate, self.config.listen());
       }
 
       info!("ping candidate: {}", candidate);
@@ -558,10 +944,10 @@ impl ApiServer {
     let response = client.get(ping_url.clone()).send().await?;
 
     if response.status().is_success() {
-      let ping_response: PingResponse = response.json().await.expect("failed to get pong back");
+      let ping_response: PingResponse = response.json().await?;
 
       if ping_response.pong == "pong" {
-        info!("Current server with port: {}, ping neighbor with url: {}", self.port, ping_url);
+        info!("Current server: {}, ping neighbor with url: {}", self.config.listen(), ping_url);
 
         neighbors.push(candidate.clone());
       }
@@ -573,13 +959,96 @@ impl ApiServer {
 
 #[cfg(test)]
 mod test {
-  use super::ApiServer;
+  use blockchain::{
+    core::{amount::Amount, error::DecodeError, raw_transaction::RawTransaction, utxo::TxOutput, wallet::Wallet},
+    utils::serializable::Serializable,
+  };
+
+  use super::{ApiServer, ConfigError, Network, NodeConfig, SignedTransaction, SignedTransactionReqDTO};
   #[test]
   fn test_neighbors() {
-    let server = ApiServer::new(8000);
+    let data_dir = std::env::temp_dir().join(format!("neighbors-{}", std::process::id()));
+    let config = NodeConfig {
+      data_dir: Some(data_dir.clone()),
+      miner_address: Some(Wallet::default().address()),
+      ..NodeConfig::default()
+    };
+    let server = ApiServer::new(config).unwrap();
 
     server.get_neighbors();
 
-    assert_eq!(1, 1)
+    assert_eq!(*server.candidates.lock().unwrap(), vec!["127.0.0.1:8001", "127.0.0.1:8002", "127.0.0.1:8003"]);
+
+    std::fs::remove_dir_all(data_dir).unwrap();
+  }
+
+  #[test]
+  fn test_miner_wallet_is_persisted() {
+    let data_dir = std::env::temp_dir().join(format!("miner-wallet-{}", std::process::id()));
+    let config = NodeConfig { data_dir: Some(data_dir.clone()), ..NodeConfig::default() };
+
+    assert!(matches!(ApiServer::load_miner_address(&config), Err(ConfigError::MissingMinerPassword)));
+
+    let config = NodeConfig { miner_password: Some("secret".into()), ..config };
+    let address = ApiServer::load_miner_address(&config).unwrap();
+    assert_eq!(ApiServer::load_miner_address(&config).unwrap(), address);
+    assert!(ApiServer::load_miner_address(&NodeConfig { miner_password: None, ..config.clone() }).is_err());
+
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+
+      let mode = std::fs::metadata(data_dir.join(ApiServer::MINER_KEYSTORE)).unwrap().permissions().mode();
+      assert_eq!(mode & 0o777, 0o600);
+    }
+
+    let payout = Wallet::default().address();
+    assert_eq!(ApiServer::load_miner_address(&NodeConfig { miner_address: Some(payout.clone()), ..config }).unwrap(), payout);
+
+    std::fs::remove_dir_all(data_dir).unwrap();
+  }
+
+  #[test]
+  fn test_genesis_is_only_loaded_when_configured() {
+    assert_eq!(ApiServer::load_genesis(None, Network::Testnet).unwrap(), Network::Testnet.genesis());
+
+    let path = std::env::temp_dir().join(format!("genesis-{}.json", std::process::id()));
+    std::fs::write(&path, serde_json::to_string(&Network::Testnet.genesis()).unwrap()).unwrap();
+
+    assert_eq!(ApiServer::load_genesis(Some(&path), Network::Testnet).unwrap(), Network::Testnet.genesis());
+    assert!(matches!(
+      ApiServer::load_genesis(Some(&path), Network::Mainnet),
+      Err(ConfigError::GenesisNetwork { expected: Network::Mainnet, found: Network::Testnet, .. })
+    ));
+
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(ApiServer::load_genesis(Some(&path), Network::Testnet), Err(ConfigError::Genesis { .. })));
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_raw_signed_transaction_decodes() {
+    let transaction = Wallet::default().sign_transaction(Wallet::default().address(), Amount::from_coins(1).unwrap(), 0, "thursque");
+    let raw = hex::encode(RawTransaction::from_transaction(&transaction).unwrap().serialize());
+
+    let request = serde_json::from_value::<SignedTransactionReqDTO>(serde_json::json!({ "raw": raw })).unwrap();
+    let SignedTransactionReqDTO::Raw { raw } = request else {
+      panic!("raw transaction parsed as {:?}", request);
+    };
+
+    let Ok(SignedTransaction::Account(decoded)) = ApiServer::decode_raw_transaction(&raw) else {
+      panic!("raw transaction did not decode to an account transaction");
+    };
+    assert_eq!(decoded.signature, transaction.signature);
+    assert_eq!(Wallet::verify_transaction(&decoded), Ok(()));
+
+    let outputs = vec![TxOutput { address: Wallet::default().address(), amount: Amount::from_coins(1).unwrap() }];
+    let utxo_trx = Wallet::default().sign_utxo_transaction(vec![], outputs, "thursque");
+    let raw = hex::encode(Serializable::serialize(&utxo_trx));
+    let Ok(SignedTransaction::Utxo(decoded)) = ApiServer::decode_raw_transaction(&raw) else {
+      panic!("raw transaction did not decode to a utxo transaction");
+    };
+    assert_eq!(decoded, utxo_trx);
+
+    assert_eq!(ApiServer::decode_raw_transaction("zz").unwrap_err(), DecodeError::InvalidField("raw"));
+  }
+}
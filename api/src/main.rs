@@ -1,25 +1,30 @@
-use core::server::ApiServer;
-use std::thread;
+use clap::Parser;
+use core::{config::{NodeArgs, NodeConfig}, server::ApiServer};
+use std::process;
 
 mod core;
 
-fn main() {
-  env_logger::init();
-  let ports = vec![8000, 8001, 8002];
-  let mut handles = vec![];
+/// Run a single blockchain node, see `node.example.toml` for the settings.
+#[derive(Parser)]
+#[command(name = "blockchain", version)]
+struct Cli {
+  #[command(flatten)]
+  node: NodeArgs,
+}
 
-  for port in ports {
-    let handle = thread::spawn(move || {
-      let runtime = tokio::runtime::Runtime::new().unwrap();
-      let server = ApiServer::new(port);
+fn main() {
+  let config = NodeConfig::load(Cli::parse().node).unwrap_or_else(|err| {
+    eprintln!("error: {}", err);
+    process::exit(1);
+  });
 
-      runtime.block_on(server.start());
-    });
+  env_logger::Builder::new().parse_filters(&config.log_level).init();
 
-    handles.push(handle);
-  }
+  let runtime = tokio::runtime::Runtime::new().unwrap();
+  let server = ApiServer::new(config).unwrap_or_else(|err| {
+    eprintln!("error: {}", err);
+    process::exit(1);
+  });
 
-  for handle in handles {
-    handle.join().unwrap();
-  }
+  runtime.block_on(server.start());
 }
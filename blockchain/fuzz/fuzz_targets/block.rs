@@ -0,0 +1,12 @@
+#![no_main]
+
+use blockchain::{core::block::Block, utils::serializable::Serializable};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+  // decoding arbitrary bytes must never panic, and whatever decodes must
+  // serialize back to the exact same bytes
+  if let Ok(decoded) = <Block as Serializable<_>>::deserialize(data) {
+    assert_eq!(Serializable::serialize(&decoded), data);
+  }
+});
@@ -3,4 +3,13 @@ pub mod block;
 pub mod raw_transaction;
 pub mod wallet;
 pub mod transaction;
-pub mod peer;
\ No newline at end of file
+pub mod peer;
+pub mod storage;
+pub mod genesis;
+pub mod error;
+pub mod amount;
+pub mod utxo;
+pub mod difficulty;
+pub mod reorg;
+pub mod block_tree;
+pub mod network;
\ No newline at end of file
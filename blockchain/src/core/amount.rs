@@ -0,0 +1,140 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::error::AmountError;
+
+/// Currency amount counted in integer base units.
+///
+/// One coin is `10^DECIMALS` base units and no amount may ever exceed
+/// `MAX_SUPPLY`, so every `Amount` in circulation is finite, non-negative and
+/// exactly representable. Amounts go over the wire as decimal strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Amount(u64);
+
+impl Amount {
+  pub const DECIMALS: u32 = 8;
+  pub const UNITS_PER_COIN: u64 = 10u64.pow(Self::DECIMALS);
+  pub const ZERO: Amount = Amount(0);
+  pub const MAX_SUPPLY: Amount = Amount(21_000_000 * Self::UNITS_PER_COIN);
+
+  pub fn from_base_units(units: u64) -> Option<Self> {
+    if units > Self::MAX_SUPPLY.0 {
+      return None;
+    }
+
+    Some(Self(units))
+  }
+
+  pub fn from_coins(coins: u64) -> Option<Self> {
+    coins.checked_mul(Self::UNITS_PER_COIN).and_then(Self::from_base_units)
+  }
+
+  pub fn base_units(&self) -> u64 {
+    self.0
+  }
+
+  pub fn is_zero(&self) -> bool {
+    self.0 == 0
+  }
+
+  pub fn checked_add(self, rhs: Amount) -> Option<Amount> {
+    self.0.checked_add(rhs.0).and_then(Self::from_base_units)
+  }
+
+  pub fn checked_sub(self, rhs: Amount) -> Option<Amount> {
+    self.0.checked_sub(rhs.0).map(Self)
+  }
+
+  pub fn saturating_sub(self, rhs: Amount) -> Amount {
+    Self(self.0.saturating_sub(rhs.0))
+  }
+}
+
+impl FromStr for Amount {
+  type Err = AmountError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let s = s.trim();
+
+    if s.is_empty() {
+      return Err(AmountError::Empty);
+    }
+
+    let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+
+    if whole.is_empty() && fraction.is_empty() {
+      return Err(AmountError::Invalid(s.to_string()));
+    }
+
+    if !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+      return Err(AmountError::Invalid(s.to_string()));
+    }
+
+    if fraction.len() > Self::DECIMALS as usize {
+      return Err(AmountError::TooPrecise(s.to_string()));
+    }
+
+    let whole_units = match whole {
+      "" => Some(0),
+      whole => whole.parse::<u64>().ok().and_then(|coins| coins.checked_mul(Self::UNITS_PER_COIN)),
+    };
+
+    let padded_fraction = format!("{:0<width$}", fraction, width = Self::DECIMALS as usize);
+    let fraction_units = padded_fraction.parse::<u64>().unwrap();
+
+    whole_units
+      .and_then(|units| units.checked_add(fraction_units))
+      .and_then(Self::from_base_units)
+      .ok_or(AmountError::ExceedsMaxSupply(s.to_string()))
+  }
+}
+
+impl Display for Amount {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let whole = self.0 / Self::UNITS_PER_COIN;
+    let fraction = self.0 % Self::UNITS_PER_COIN;
+
+    if fraction == 0 {
+      return write!(f, "{}", whole);
+    }
+
+    let fraction = format!("{:0width$}", fraction, width = Self::DECIMALS as usize);
+
+    write!(f, "{}.{}", whole, fraction.trim_end_matches('0'))
+  }
+}
+
+impl Serialize for Amount {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&self.to_string())
+  }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let s = String::deserialize(deserializer)?;
+
+    s.parse().map_err(de::Error::custom)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::Amount;
+  use crate::core::error::AmountError;
+
+  #[test]
+  fn test_parse_and_format() {
+    assert_eq!("1.5".parse::<Amount>().unwrap().base_units(), 150_000_000);
+    assert_eq!(".00000001".parse::<Amount>().unwrap().base_units(), 1);
+    assert_eq!(Amount::from_base_units(150_000_000).unwrap().to_string(), "1.5");
+    assert_eq!(Amount::from_coins(3).unwrap().to_string(), "3");
+
+    assert!(matches!("0.000000001".parse::<Amount>(), Err(AmountError::TooPrecise(_))));
+    assert!(matches!("-1".parse::<Amount>(), Err(AmountError::Invalid(_))));
+    assert!(matches!("NaN".parse::<Amount>(), Err(AmountError::Invalid(_))));
+    assert!(matches!("21000000.1".parse::<Amount>(), Err(AmountError::ExceedsMaxSupply(_))));
+    assert_eq!(Amount::MAX_SUPPLY.checked_add(Amount::from_base_units(1).unwrap()), None);
+  }
+}
@@ -1,7 +1,14 @@
 use std::{ops::AddAssign, time::SystemTime};
 use serde::{Deserialize, Serialize};
 
-use crate::{core::raw_transaction::RawTransaction, utils::{hash::hash, serializable::Serializable}};
+use crate::{
+  core::{difficulty::meets_difficulty, error::DecodeError, raw_transaction::RawTransaction, utxo::UtxoTransaction},
+  utils::{
+    hash::hash,
+    merkle::{merkle_proof, merkle_root, MerkleProof},
+    serializable::{write_field, FieldReader, Serializable},
+  },
+};
 
 pub enum BlockSearch {
   SearchByIndex(usize),
@@ -23,11 +30,46 @@ pub enum BlockSearchResult <'a> {
   FailOfTransaction(Vec<u8>)
 }
 
+/// Everything the block hash commits to. Transactions are only committed to
+/// through `merkle_root`, so a header alone is enough to check an inclusion
+/// proof.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockHeader {
+  pub version: u32,
+  pub previous_hash: Vec<u8>,
+  pub merkle_root: Vec<u8>,
+  pub timestamp: u128,
+  /// leading zero bits the header hash needs
+  pub difficulty: u32,
+  pub nonce: u32,
+}
+
+impl BlockHeader {
+  pub const VERSION: u32 = 1;
+  /// How far past local time a block may be stamped, so miners cannot stretch
+  /// a retarget window by dating blocks ahead.
+  pub const MAX_FUTURE_DRIFT_NANOS: u128 = 2 * 60 * 1_000_000_000;
+
+  pub fn hash(&self) -> Vec<u8> {
+    let mut bin = Vec::new();
+    bin.extend(self.version.to_be_bytes());
+    bin.extend(self.previous_hash.clone());
+    bin.extend(self.merkle_root.clone());
+    bin.extend(self.timestamp.to_be_bytes());
+    bin.extend(self.difficulty.to_be_bytes());
+    bin.extend(self.nonce.to_be_bytes());
+
+    hash(bin)
+  }
+
+  pub fn meets_difficulty(&self) -> bool {
+    meets_difficulty(&self.hash(), self.difficulty)
+  }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Block {
-  pub nonce: u32,
-	pub previous_hash: Vec<u8>,
-	pub timestamp: u128,
+  pub header: BlockHeader,
 	pub transactions: Vec<Vec<u8>>
 }
 
@@ -35,39 +77,119 @@ impl Block {
 	pub fn new(nonce: u32, previous_hash: Vec<u8>) -> Self {
 		let time_now = SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap();
 
-		Self { nonce, previous_hash, timestamp: time_now.as_nanos(), transactions: Vec::<Vec<u8>>::new() }
+		let header = BlockHeader {
+			version: BlockHeader::VERSION,
+			previous_hash,
+			merkle_root: merkle_root(&[]),
+			timestamp: time_now.as_nanos(),
+			difficulty: 0,
+			nonce,
+		};
+
+		Self { header, transactions: Vec::<Vec<u8>>::new() }
+	}
+
+	/// Commit the header to the current transactions, needed after changing them.
+	pub fn update_merkle_root(&mut self) {
+		self.header.merkle_root = merkle_root(&self.transactions);
+	}
+
+	pub fn has_valid_merkle_root(&self) -> bool {
+		self.header.merkle_root == merkle_root(&self.transactions)
+	}
+
+	pub fn merkle_proof(&self, index: usize) -> Option<MerkleProof> {
+		merkle_proof(&self.transactions, index)
 	}
 
 	pub fn print(&self) {
-		println!("timestamp: {:x}", self.timestamp);
-		println!("nonce: {}", self.nonce);
-		println!("previous_hash: {:?}", self.previous_hash);
+		println!("version: {}", self.header.version);
+		println!("timestamp: {:x}", self.header.timestamp);
+		println!("difficulty: {}", self.header.difficulty);
+		println!("nonce: {}", self.header.nonce);
+		println!("previous_hash: {:?}", self.header.previous_hash);
+		println!("merkle_root: {:?}", self.header.merkle_root);
 		println!("transactions: {:?}", self.transactions);
 
     for (idx, tx) in self.transactions.iter().enumerate() {
-      let deserialized = RawTransaction::deserialize(tx.clone());
-
-      println!("the {}'th transaction is: {}", idx, deserialized);
+      if let Ok(deserialized) = <RawTransaction as Serializable<_>>::deserialize(tx) {
+        println!("the {}'th transaction is: {}", idx, deserialized);
+      } else if let Ok(deserialized) = <UtxoTransaction as Serializable<_>>::deserialize(tx) {
+        println!("the {}'th transaction is: {:?}", idx, deserialized);
+      }
     }
 	}
 
   pub fn hash(&self) -> Vec<u8> {
-    let mut bin = Vec::new();
-    bin.extend(self.nonce.to_be_bytes());
-    bin.extend(self.previous_hash.clone());
-    bin.extend(self.timestamp.to_be_bytes());
+    self.header.hash()
+  }
+}
+
+impl Serializable <Block> for Block {
+  const VERSION: u8 = 1;
+
+  fn serialize(&self) -> Vec<u8> {
+    let mut serialized = vec![<Self as Serializable<Block>>::VERSION];
+
+    write_field(&mut serialized, &self.header.version.to_be_bytes());
+    write_field(&mut serialized, &self.header.previous_hash);
+    write_field(&mut serialized, &self.header.merkle_root);
+    write_field(&mut serialized, &self.header.timestamp.to_be_bytes());
+    write_field(&mut serialized, &self.header.difficulty.to_be_bytes());
+    write_field(&mut serialized, &self.header.nonce.to_be_bytes());
+
+    write_field(&mut serialized, &(self.transactions.len() as u64).to_be_bytes());
 
     for tx in self.transactions.iter() {
-      bin.extend(tx.clone());
+      write_field(&mut serialized, tx);
     }
 
-    hash(bin)
+    serialized
+  }
+
+  fn deserialize(bytes: &[u8]) -> Result<Block, DecodeError> {
+    let mut reader = FieldReader::new(bytes);
+
+    reader.read_version(<Self as Serializable<Block>>::VERSION)?;
+
+    let header = BlockHeader {
+      version: u32::from_be_bytes(reader.read_array("version")?),
+      previous_hash: reader.read_field()?.to_vec(),
+      merkle_root: reader.read_field()?.to_vec(),
+      timestamp: u128::from_be_bytes(reader.read_array("timestamp")?),
+      difficulty: u32::from_be_bytes(reader.read_array("difficulty")?),
+      nonce: u32::from_be_bytes(reader.read_array("nonce")?),
+    };
+
+    // every transaction takes at least its length prefix, so a count larger
+    // than the remaining bytes is caught by the reader before allocating
+    let transaction_count = reader.read_u64("transaction_count")?;
+    let mut transactions = vec![];
+
+    for _ in 0..transaction_count {
+      transactions.push(reader.read_field()?.to_vec());
+    }
+
+    reader.finish()?;
+
+    Ok(Block { header, transactions })
   }
 }
 
+/// Advance the nonce while mining. Once the nonce space is exhausted the
+/// nonce wraps around and the timestamp moves forward, so the header never
+/// repeats at difficulties above 32 bits.
 impl AddAssign<u32> for Block {
   fn add_assign(&mut self, rhs: u32) {
-    self.nonce += rhs;
+    let (nonce, wrapped) = self.header.nonce.overflowing_add(rhs);
+
+    self.header.nonce = nonce;
+
+    if wrapped {
+      let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+
+      self.header.timestamp = now.max(self.header.timestamp.saturating_add(1));
+    }
   }
 }
 
@@ -75,8 +197,43 @@ impl PartialEq for Block {
   fn eq(&self, other: &Self) -> bool {
     self.hash() == other.hash()
   }
+}
+
+#[cfg(test)]
+mod test {
+  use super::Block;
+  use crate::{core::error::DecodeError, utils::serializable::Serializable};
+
+  #[test]
+  fn test_block_roundtrip() {
+    let mut block = Block::new(7, vec![1; 32]);
+    block.transactions = vec![vec![1, 2, 3], vec![]];
+    block.update_merkle_root();
+
+    let serialized = Serializable::serialize(&block);
+    let restored = <Block as Serializable<_>>::deserialize(&serialized).unwrap();
 
-  fn ne(&self, other: &Self) -> bool {
-    self.hash() != other.hash()
+    assert_eq!(restored.header, block.header);
+    assert_eq!(restored.transactions, block.transactions);
+
+    let truncated = <Block as Serializable<_>>::deserialize(&serialized[..serialized.len() - 1]);
+    assert_eq!(truncated.unwrap_err(), DecodeError::UnexpectedEnd);
+
+    let mut oversized = serialized[..serialized.len() - 8].to_vec();
+    oversized.extend(u64::MAX.to_be_bytes());
+    assert_eq!(<Block as Serializable<_>>::deserialize(&oversized).unwrap_err(), DecodeError::FieldTooLong(u64::MAX));
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_nonce_wraps_into_a_new_timestamp() {
+    let mut block = Block::new(u32::MAX - 1, vec![1; 32]);
+    let timestamp = block.header.timestamp;
+
+    block += 1;
+    assert_eq!((block.header.nonce, block.header.timestamp), (u32::MAX, timestamp));
+
+    block += 1;
+    assert_eq!(block.header.nonce, 0);
+    assert!(block.header.timestamp > timestamp);
+  }
+}
@@ -0,0 +1,380 @@
+use std::{cmp::Ordering, collections::HashMap};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use super::{
+  block::{Block, BlockHeader},
+  blockchain::BlocksChain,
+  difficulty::{self, Schedule},
+  error::BlockValidationError,
+  reorg::ReorgEvent,
+};
+
+/// Orphans are kept in memory until their parent shows up, so their number
+/// is capped to keep peers from filling it with junk.
+pub const MAX_ORPHANS: usize = 256;
+
+/// Side branches ending further than this below the local tip are pruned.
+pub const MAX_FORK_DEPTH: usize = 100;
+
+/// Side branches kept at most, the lightest ones beyond it are pruned.
+pub const MAX_SIDE_BRANCHES: usize = 16;
+
+/// Chain tip known to the tree with the cumulative work of its branch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tip {
+  pub hash: Vec<u8>,
+  pub height: usize,
+  pub work: u128,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InsertOutcome {
+  /// the block was seen before
+  Known,
+  /// the parent is unknown, the block waits in the orphan pool
+  Orphaned,
+  /// the block and every orphan it unblocked, parents first
+  Connected(Vec<Vec<u8>>),
+}
+
+/// What became of a block handed to `Blockchain::receive_block`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockAcceptance {
+  Known,
+  Orphaned,
+  /// connected, but the local chain still has more work
+  SideBranch,
+  /// the local chain now ends in the block's branch
+  Accepted(ReorgEvent),
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+  block: Block,
+  height: usize,
+  work: u128,
+}
+
+/// Every block connected to the genesis block, side branches included, plus
+/// the orphans still waiting for their parent. Blocks are keyed by hash.
+///
+/// The tree only checks what headers can prove: that a block has the
+/// difficulty expected after its parent, meets it and commits to its
+/// transactions. Whether a branch follows the consensus rules is up to
+/// `Blockchain::validate_chain`.
+#[derive(Debug, Clone)]
+pub struct BlockTree {
+  nodes: HashMap<Vec<u8>, Node>,
+  orphans: HashMap<Vec<u8>, Block>,
+  tips: Vec<Vec<u8>>,
+  schedule: Schedule,
+}
+
+impl BlockTree {
+  pub fn new(genesis: Block, schedule: Schedule) -> Self {
+    let hash = genesis.hash();
+    let node = Node { work: difficulty::work(genesis.header.difficulty), block: genesis, height: 0 };
+
+    Self { nodes: HashMap::from([(hash.clone(), node)]), orphans: HashMap::new(), tips: vec![hash], schedule }
+  }
+
+  pub fn contains(&self, hash: &[u8]) -> bool {
+    self.nodes.contains_key(hash)
+  }
+
+  pub fn get(&self, hash: &[u8]) -> Option<&Block> {
+    self.nodes.get(hash).map(|node| &node.block)
+  }
+
+  pub fn orphan_count(&self) -> usize {
+    self.orphans.len()
+  }
+
+  pub fn insert(&mut self, block: Block) -> Result<InsertOutcome, BlockValidationError> {
+    let hash = block.hash();
+
+    if self.nodes.contains_key(&hash) || self.orphans.contains_key(&hash) {
+      return Ok(InsertOutcome::Known);
+    }
+
+    if block.header.version != BlockHeader::VERSION {
+      return Err(BlockValidationError::UnsupportedVersion(block.header.version));
+    }
+
+    if !block.has_valid_merkle_root() {
+      return Err(BlockValidationError::MerkleRootMismatch);
+    }
+
+    if !block.header.meets_difficulty() {
+      return Err(BlockValidationError::InsufficientProofOfWork);
+    }
+
+    if !self.nodes.contains_key(&block.header.previous_hash) {
+      if self.orphans.len() >= MAX_ORPHANS {
+        warn!("orphan pool is full, dropping block {}", hex::encode(&hash));
+      } else {
+        self.orphans.insert(hash, block);
+      }
+
+      return Ok(InsertOutcome::Orphaned);
+    }
+
+    self.check_difficulty(&block)?;
+
+    let mut connected = vec![];
+    let mut pending = vec![block];
+
+    while let Some(block) = pending.pop() {
+      if let Err(err) = self.check_difficulty(&block) {
+        warn!("dropping orphan {}: {}", hex::encode(block.hash()), err);
+
+        continue;
+      }
+
+      let hash = self.connect(block);
+
+      let children: Vec<Vec<u8>> = self.orphans
+        .iter()
+        .filter(|(_, orphan)| orphan.header.previous_hash == hash)
+        .map(|(orphan_hash, _)| orphan_hash.clone())
+        .collect();
+
+      for child in children {
+        pending.extend(self.orphans.remove(&child));
+      }
+
+      connected.push(hash);
+    }
+
+    Ok(InsertOutcome::Connected(connected))
+  }
+
+  /// Difficulty of the block following `parent`, see `Schedule`.
+  fn expected_difficulty(&self, parent: &[u8]) -> u32 {
+    let parent = &self.nodes[parent];
+
+    let Some(depth) = self.schedule.window(parent.height as u64 + 1) else {
+      return parent.block.header.difficulty;
+    };
+
+    let mut first = parent;
+
+    for _ in 0..depth {
+      first = &self.nodes[&first.block.header.previous_hash];
+    }
+
+    self.schedule.retarget(&parent.block.header, &first.block.header)
+  }
+
+  fn check_difficulty(&self, block: &Block) -> Result<(), BlockValidationError> {
+    let expected = self.expected_difficulty(&block.header.previous_hash);
+
+    if block.header.difficulty != expected {
+      return Err(BlockValidationError::UnexpectedDifficulty { expected, found: block.header.difficulty });
+    }
+
+    Ok(())
+  }
+
+  fn connect(&mut self, block: Block) -> Vec<u8> {
+    let hash = block.hash();
+    let parent = &self.nodes[&block.header.previous_hash];
+
+    let node = Node {
+      height: parent.height + 1,
+      work: parent.work.saturating_add(difficulty::work(block.header.difficulty)),
+      block,
+    };
+
+    self.tips.retain(|tip| *tip != node.block.header.previous_hash);
+    self.tips.push(hash.clone());
+    self.nodes.insert(hash.clone(), node);
+
+    hash
+  }
+
+  /// Drop `hash` and everything built on top of it, used once a branch turns
+  /// out to break the consensus rules.
+  pub fn discard(&mut self, hash: &[u8]) {
+    let mut pending = vec![hash.to_vec()];
+
+    while let Some(hash) = pending.pop() {
+      let Some(node) = self.nodes.remove(&hash) else {
+        continue;
+      };
+
+      self.tips.retain(|tip| *tip != hash);
+
+      pending.extend(self.nodes
+        .iter()
+        .filter(|(_, child)| child.block.header.previous_hash == hash)
+        .map(|(child_hash, _)| child_hash.clone()));
+
+      let parent = &node.block.header.previous_hash;
+      let parent_has_children = self.nodes.values().any(|child| child.block.header.previous_hash == *parent);
+
+      if self.nodes.contains_key(parent) && !parent_has_children && !self.tips.contains(parent) {
+        self.tips.push(parent.clone());
+      }
+    }
+  }
+
+  /// Drop side branches that end more than `MAX_FORK_DEPTH` blocks below
+  /// `keep`, and the lightest ones beyond `MAX_SIDE_BRANCHES`, so peers
+  /// cannot grow the tree without doing the work of the local chain.
+  pub fn prune(&mut self, keep: &[u8]) {
+    let Some(height) = self.nodes.get(keep).map(|node| node.height) else {
+      return;
+    };
+
+    let stale: Vec<Vec<u8>> = self.tips()
+      .into_iter()
+      .filter(|tip| tip.hash != keep)
+      .enumerate()
+      .filter(|(index, tip)| *index >= MAX_SIDE_BRANCHES || tip.height + MAX_FORK_DEPTH < height)
+      .map(|(_, tip)| tip.hash)
+      .collect();
+
+    for tip in stale {
+      // walk down to the block the branch forked off at, whose other child
+      // leads towards `keep`
+      let mut root = tip;
+
+      loop {
+        let parent = self.nodes[&root].block.header.previous_hash.clone();
+        let siblings = self.nodes.values().filter(|node| node.block.header.previous_hash == parent).count();
+
+        if siblings > 1 || parent == keep || !self.nodes.contains_key(&parent) {
+          break;
+        }
+
+        root = parent;
+      }
+
+      self.discard(&root);
+    }
+  }
+
+  pub fn tips(&self) -> Vec<Tip> {
+    let mut tips: Vec<Tip> = self.tips
+      .iter()
+      .map(|hash| {
+        let node = &self.nodes[hash];
+
+        Tip { hash: hash.clone(), height: node.height, work: node.work }
+      })
+      .collect();
+
+    tips.sort_by(|a, b| Self::compare_tips(b, a));
+
+    tips
+  }
+
+  /// Tip winning the fork choice of `Blockchain::compare_chains`.
+  pub fn best_tip(&self) -> Tip {
+    self.tips().remove(0)
+  }
+
+  fn compare_tips(a: &Tip, b: &Tip) -> Ordering {
+    a.work.cmp(&b.work).then_with(|| b.hash.cmp(&a.hash))
+  }
+
+  /// Blocks from the genesis block up to `tip`.
+  pub fn branch(&self, tip: &[u8]) -> Option<BlocksChain> {
+    let mut node = self.nodes.get(tip)?;
+    let mut branch = vec![node.block.clone()];
+
+    while node.height > 0 {
+      node = &self.nodes[&node.block.header.previous_hash];
+      branch.push(node.block.clone());
+    }
+
+    branch.reverse();
+
+    Some(branch)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{BlockTree, InsertOutcome, MAX_FORK_DEPTH};
+  use crate::core::{block::Block, blockchain::Blockchain, error::BlockValidationError, genesis::GenesisSpec};
+
+  fn child_of(parent: &Block, difficulty: u32) -> Block {
+    let mut block = Block::new(0, parent.hash());
+    block.header.difficulty = difficulty;
+    block.update_merkle_root();
+
+    while !block.header.meets_difficulty() {
+      block += 1;
+    }
+
+    block
+  }
+
+  fn tree() -> (BlockTree, Block) {
+    let spec = GenesisSpec { difficulty: 1, retarget_interval: u64::MAX, ..GenesisSpec::default() };
+    let genesis = spec.block();
+
+    (BlockTree::new(genesis.clone(), spec.schedule()), genesis)
+  }
+
+  #[test]
+  fn test_orphans_connect_and_tips_are_tracked() {
+    let (mut tree, genesis) = tree();
+
+    let first = child_of(&genesis, 1);
+    let second = child_of(&first, 1);
+    let side = child_of(&genesis, 1);
+    let side_second = child_of(&side, 1);
+    let side_third = child_of(&side_second, 1);
+
+    assert_eq!(tree.insert(second.clone()), Ok(InsertOutcome::Orphaned));
+    assert_eq!(tree.orphan_count(), 1);
+    assert_eq!(tree.insert(first.clone()), Ok(InsertOutcome::Connected(vec![first.hash(), second.hash()])));
+    assert_eq!(tree.orphan_count(), 0);
+    assert_eq!(tree.insert(side_third.clone()), Ok(InsertOutcome::Orphaned));
+    assert_eq!(tree.insert(side_second.clone()), Ok(InsertOutcome::Orphaned));
+    assert_eq!(tree.insert(side.clone()), Ok(InsertOutcome::Connected(vec![side.hash(), side_second.hash(), side_third.hash()])));
+    assert_eq!(tree.insert(side.clone()), Ok(InsertOutcome::Known));
+
+    let tips = tree.tips();
+    assert_eq!(tips.len(), 2);
+    assert_eq!(tips[0].hash, side_third.hash());
+    assert_eq!(tree.branch(&second.hash()).unwrap(), vec![genesis.clone(), first.clone(), second.clone()]);
+    assert_eq!(
+      Blockchain::compare_chains(&tree.branch(&side_third.hash()).unwrap(), &[genesis, first, second]),
+      std::cmp::Ordering::Greater,
+    );
+
+    tree.discard(&side.hash());
+    assert_eq!(tree.tips().len(), 1);
+  }
+
+  #[test]
+  fn test_unexpected_difficulty_and_stale_branches_are_dropped() {
+    let (mut tree, genesis) = tree();
+
+    assert_eq!(
+      tree.insert(child_of(&genesis, 2)),
+      Err(BlockValidationError::UnexpectedDifficulty { expected: 1, found: 2 }),
+    );
+
+    let side = child_of(&genesis, 1);
+    assert!(matches!(tree.insert(side.clone()), Ok(InsertOutcome::Connected(_))));
+
+    let mut tip = genesis;
+    for _ in 0..=MAX_FORK_DEPTH + 1 {
+      tip = child_of(&tip, 1);
+      tree.insert(tip.clone()).unwrap();
+    }
+
+    tree.prune(&tip.hash());
+    assert!(tree.contains(&tip.hash()));
+    assert!(!tree.contains(&side.hash()));
+    assert_eq!(tree.tips().len(), 1);
+  }
+}
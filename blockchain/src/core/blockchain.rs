@@ -1,11 +1,22 @@
-use std::{ops::Index, time::Instant};
+use std::{cmp::Ordering, collections::{HashMap, HashSet}, ops::Index, path::Path, time::{Instant, SystemTime, UNIX_EPOCH}};
 
-use crate::utils::serializable::Serializable;
+use log::{debug, info, warn};
+
+use crate::utils::{address::{self, LedgerKey}, serializable::Serializable};
 
 use super::{
-	block::{Block, BlockSearch, BlockSearchResult},
+	amount::Amount,
+	block::{Block, BlockHeader, BlockSearch, BlockSearchResult},
+	block_tree::{BlockAcceptance, BlockTree, InsertOutcome, Tip},
+	difficulty,
+	error::{BlockValidationError, BlockchainError, ChainValidationError, ReceiveBlockError, TransactionError},
+	genesis::GenesisSpec,
+	network::Network,
 	raw_transaction::RawTransaction,
+	reorg::ReorgEvent,
+	storage::BlockStore,
 	transaction::Transaction,
+	utxo::{LedgerMode, OutPoint, TxOutput, UtxoSet, UtxoTransaction},
 	wallet::Wallet,
 };
 
@@ -14,38 +25,299 @@ pub struct Blockchain {
 	pub transaction_pool: Vec<Vec<u8>>,
 	pub chain: Vec<Block>,
 	address: String,
+	genesis: GenesisSpec,
+	genesis_hash: Vec<u8>,
+	/// next nonce each address has to use, counting mined transactions only
+	nonces: HashMap<LedgerKey, u64>,
+	/// balances of the mined chain, only filled in `LedgerMode::Account`
+	balances: HashMap<LedgerKey, Amount>,
+	/// unspent outputs of the mined chain, only filled in `LedgerMode::Utxo`
+	utxo_set: UtxoSet,
+	store: Option<BlockStore>,
+	/// every known block including side branches and orphans
+	tree: BlockTree,
 }
 
 pub type BlocksChain = Vec<Block>;
 
 impl Blockchain {
-	const DIFFICULTY: usize = 4;
-	const MINING_SENDER: &'static str = "0xEA31cD0D90fC35E7Af05ED42B779C3E3Aa45C0Dc";
-	const MINING_REWARD: i64 = 1;
+	pub const MINING_SENDER: &'static str = "0xEA31cD0D90fC35E7Af05ED42B779C3E3Aa45C0Dc";
 
 	pub fn new(address: String) -> Self {
+		Self::with_genesis(address, GenesisSpec::default())
+	}
+
+	pub fn with_genesis(address: String, genesis: GenesisSpec) -> Self {
+		let genesis_block = genesis.block();
+
 		let mut blockchain = Self {
 				transaction_pool: Vec::<Vec<u8>>::new(),
-				chain: vec![],
+				genesis_hash: genesis_block.hash(),
+				tree: BlockTree::new(genesis_block.clone(), genesis.schedule()),
+				chain: vec![genesis_block],
 				address,
+				genesis,
+				nonces: HashMap::new(),
+				balances: HashMap::new(),
+				utxo_set: UtxoSet::default(),
+				store: None,
 		};
 
-		let genesis_block = Self::create_genesis_block();
-		blockchain.chain.push(genesis_block);
+		blockchain.rebuild_state();
 
 		blockchain
 	}
 
-	fn create_genesis_block() -> Block {
-			Block::new(0, vec![0 as u8; 32])
+	/// Open a blockchain backed by the block store in `data_dir`.
+	///
+	/// A fresh store is seeded with the genesis block, an existing one is reloaded
+	/// and has to pass `chain_is_valid` before the node is allowed to use it.
+	pub fn open<P: AsRef<Path>>(address: String, genesis: GenesisSpec, data_dir: P) -> Result<Self, BlockchainError> {
+		let mut store = BlockStore::open(data_dir.as_ref().join("chain"))?;
+		let mut blockchain = Self::with_genesis(address, genesis);
+
+		if store.is_empty() {
+			store.append(&blockchain.chain[0])?;
+		} else {
+			blockchain.chain = store.load_blocks()?;
+		}
+
+		blockchain.validate_chain(&blockchain.chain)?;
+
+		blockchain.store = Some(store);
+		blockchain.rebuild_state();
+
+		Ok(blockchain)
+	}
+
+	pub fn genesis(&self) -> &GenesisSpec {
+		&self.genesis
+	}
+
+	/// Address the mining rewards of this node are paid to.
+	pub fn miner_address(&self) -> &str {
+		&self.address
+	}
+
+	pub fn chain_id(&self) -> &str {
+		&self.genesis.chain_id
+	}
+
+	pub fn network(&self) -> Network {
+		self.genesis.network
+	}
+
+	pub fn genesis_hash(&self) -> Vec<u8> {
+		self.genesis_hash.clone()
+	}
+
+	/// Whether `chain` starts from the same genesis block as this node.
+	pub fn shares_genesis(&self, chain: &BlocksChain) -> bool {
+		chain.first().map(Block::hash) == Some(self.genesis_hash.clone())
+	}
+
+	/// Reorganize onto `chain`: roll back the local blocks past the common
+	/// ancestor, rewriting only those in the store, and return their
+	/// transactions to the pool unless the new branch already includes them.
+	/// The pool is revalidated against the new chain afterwards.
+	pub fn replace_chain(&mut self, chain: BlocksChain) -> Result<ReorgEvent, BlockchainError> {
+		let fork_height = self.chain
+			.iter()
+			.zip(chain.iter())
+			.take_while(|(own, other)| own == other)
+			.count();
+
+		if let Some(store) = self.store.as_mut() {
+			store.truncate(fork_height)?;
+
+			for block in chain.iter().skip(fork_height) {
+				store.append(block)?;
+			}
+		}
+
+		let old_tip = self.last_block().map(Block::hash).unwrap_or_default();
+		let depth = self.chain.len() - fork_height;
+
+		let orphaned: Vec<Vec<u8>> = self.chain
+			.drain(fork_height..)
+			.flat_map(|block| block.transactions)
+			.filter(|tx| !Self::is_mining_reward(tx))
+			.collect();
+
+		let included: HashSet<&Vec<u8>> = chain
+			.iter()
+			.skip(fork_height)
+			.flat_map(|block| block.transactions.iter())
+			.collect();
+
+		let mut pool = vec![];
+
+		for tx in orphaned.iter().chain(self.transaction_pool.iter()) {
+			if !included.contains(tx) && !pool.contains(tx) {
+				pool.push(tx.clone());
+			}
+		}
+
+		self.transaction_pool = pool;
+		self.chain = chain;
+		self.rebuild_state();
+		self.prune_transaction_pool();
+
+		let event = ReorgEvent {
+			common_ancestor: fork_height.saturating_sub(1),
+			depth,
+			old_tip,
+			new_tip: self.last_block().map(Block::hash).unwrap_or_default(),
+			reinjected: orphaned.iter().filter(|tx| self.transaction_pool.contains(tx)).count(),
+		};
+
+		if event.depth > 0 {
+			info!("{}", event);
+		}
+
+		Ok(event)
+	}
+
+	/// Take a block announced by a peer. It goes into the block tree, and if
+	/// that makes another branch the heaviest, the node reorganizes onto it
+	/// once the branch passes `validate_chain`. An invalid branch is dropped
+	/// from the tree.
+	pub fn receive_block(&mut self, block: Block) -> Result<BlockAcceptance, ReceiveBlockError> {
+		match self.tree.insert(block).map_err(ReceiveBlockError::Rejected)? {
+			InsertOutcome::Known => return Ok(BlockAcceptance::Known),
+			InsertOutcome::Orphaned => return Ok(BlockAcceptance::Orphaned),
+			InsertOutcome::Connected(_) => {}
+		}
+
+		let acceptance = self.adopt_best_branch();
+
+		if let Some(tip) = self.last_block().map(Block::hash) {
+			self.tree.prune(&tip);
+		}
+
+		acceptance
+	}
+
+	/// Reorganize onto the heaviest branch of the tree. Branches that fail
+	/// `validate_chain` are dropped and the next heaviest one is tried, until
+	/// one passes or none has more work than the local chain.
+	fn adopt_best_branch(&mut self) -> Result<BlockAcceptance, ReceiveBlockError> {
+		let mut rejected = None;
+
+		loop {
+			let best_tip = self.tree.best_tip();
+
+			if self.last_block().is_some_and(|block| block.hash() == best_tip.hash) {
+				break;
+			}
+
+			let best_branch = self.tree.branch(&best_tip.hash).unwrap();
+
+			if !self.is_heavier_chain(&best_branch) {
+				break;
+			}
+
+			if let Err(err) = self.validate_chain(&best_branch) {
+				self.tree.discard(&best_branch[err.height].hash());
+				rejected.get_or_insert(err);
+
+				continue;
+			}
+
+			return self.replace_chain(best_branch)
+				.map(BlockAcceptance::Accepted)
+				.map_err(|err| ReceiveBlockError::Storage(err.to_string()));
+		}
+
+		match rejected {
+			Some(err) => Err(ReceiveBlockError::Invalid(err)),
+			None => Ok(BlockAcceptance::SideBranch),
+		}
+	}
+
+	pub fn tips(&self) -> Vec<Tip> {
+		self.tree.tips()
+	}
+
+	fn is_mining_reward(tx: &[u8]) -> bool {
+		if let Ok(utxo_trx) = UtxoTransaction::deserialize(tx) {
+			return utxo_trx.is_coinbase();
+		}
+
+		RawTransaction::deserialize(tx).is_ok_and(|raw_trx| raw_trx.sender_address == Self::MINING_SENDER.as_bytes())
+	}
+
+	/// Next nonce `address` has to sign with, including its transactions that
+	/// are still waiting in the pool.
+	pub fn next_nonce(&self, address: &str) -> u64 {
+		let key = address::ledger_key(address);
+
+		let pending = self.transaction_pool
+			.iter()
+			.filter(|tx| RawTransaction::deserialize(tx).is_ok_and(|raw_trx| Self::ledger_key(&raw_trx.sender_address) == key))
+			.count() as u64;
+
+		self.nonces.get(&key).copied().unwrap_or_default() + pending
+	}
+
+	/// Address bytes of a stored transaction in the form balances and nonces
+	/// are kept under, so both address formats of a key share them.
+	fn ledger_key(address_bytes: &[u8]) -> LedgerKey {
+		address::ledger_key(&String::from_utf8_lossy(address_bytes))
+	}
+
+	pub fn ledger(&self) -> LedgerMode {
+		self.genesis.ledger
+	}
+
+	/// Recompute the nonces, balances and UTXO set kept alongside the chain.
+	fn rebuild_state(&mut self) {
+		self.nonces.clear();
+		self.balances.clear();
+		self.utxo_set.clear();
+
+		for block in self.chain.clone().iter() {
+			self.record_block(block);
+		}
+	}
+
+	fn record_block(&mut self, block: &Block) {
+		if let Err(err) = self.tree.insert(block.clone()) {
+			warn!("block {} not added to the block tree: {}", hex::encode(block.hash()), err);
+		}
+
+		for tx in block.transactions.iter() {
+			if let Ok(utxo_trx) = UtxoTransaction::deserialize(tx) {
+				self.utxo_set.apply(&utxo_trx);
+
+				continue;
+			}
+
+			// blocks are validated before they are recorded, so this only skips junk
+			let Ok(raw_trx) = RawTransaction::deserialize(tx) else {
+				continue;
+			};
+
+			let sender = Self::ledger_key(&raw_trx.sender_address);
+			let receiver = self.balances.entry(Self::ledger_key(&raw_trx.recipient_address)).or_default();
+			*receiver = receiver.checked_add(raw_trx.value).unwrap_or(Amount::MAX_SUPPLY);
+
+			if raw_trx.sender_address != Self::MINING_SENDER.as_bytes() {
+				let balance = self.balances.entry(sender.clone()).or_default();
+				*balance = balance.saturating_sub(raw_trx.value);
+
+				self.nonces.insert(sender, raw_trx.nonce + 1);
+			}
+		}
 	}
 
-	pub fn create_block(&mut self, nonce: u32, previous_hash: Vec<u8>) {
+	pub fn create_block(&mut self, nonce: u32, previous_hash: Vec<u8>) -> Result<(), BlockchainError> {
 			let mut block = Block::new(nonce, previous_hash);
 
 			// add current transactions in the transaction pool into the new block
 			block.transactions = self.transaction_pool.clone();
-			self.transaction_pool.clear();
+			block.header.difficulty = self.expected_difficulty(&self.chain);
+			block.update_merkle_root();
 
 			let start_time = Instant::now();
 
@@ -53,13 +325,17 @@ impl Blockchain {
 
 			let time_elapsed = start_time.elapsed();
 
-			println!(
-					"Time taken to mine block: {}\nBlock hash: {}",
-					time_elapsed.as_secs_f32(),
-					block_hash
-			);
+			debug!("mined block {} in {}s", block_hash, time_elapsed.as_secs_f32());
 
+			if let Some(store) = self.store.as_mut() {
+					store.append(&block)?;
+			}
+
+			self.transaction_pool.clear();
+			self.record_block(&block);
 			self.chain.push(block);
+
+			Ok(())
 	}
 
 	pub fn print(&self) {
@@ -74,21 +350,22 @@ impl Blockchain {
 	pub fn get_transactions(&self) -> Vec<Transaction> {
 			let mut transactions = Vec::<Transaction>::new();
 
-			for transaction in self.transaction_pool.clone() {
-					let raw_transaction = RawTransaction::deserialize(transaction);
-
-					transactions.push(Transaction {
-							sender: String::from_utf8(raw_transaction.sender_address).unwrap(),
-							receiver: String::from_utf8(raw_transaction.recipient_address).unwrap(),
-							amount: raw_transaction.value as f64,
-							public_key: String::new(),
-							signature: String::new(),
-					});
+			for transaction in self.transaction_pool.iter() {
+					if let Ok(transaction) = RawTransaction::deserialize(transaction).and_then(|raw_trx| raw_trx.to_transaction()) {
+							transactions.push(transaction);
+					}
 			}
 
 			transactions
 	}
 
+	pub fn get_utxo_transactions(&self) -> Vec<UtxoTransaction> {
+			self.transaction_pool
+					.iter()
+					.filter_map(|transaction| UtxoTransaction::deserialize(transaction).ok())
+					.collect()
+	}
+
 	/// Get last block
 	pub fn last_block(&self) -> Option<&Block> {
 			if self.chain.len() > 1 {
@@ -98,7 +375,7 @@ impl Blockchain {
 			}
 	}
 
-	pub fn search_block(&self, search: BlockSearch) -> BlockSearchResult {
+	pub fn search_block(&self, search: BlockSearch) -> BlockSearchResult<'_> {
 			for (idx, block) in self.chain.iter().enumerate() {
 					match search {
 							// Search by index
@@ -114,7 +391,7 @@ impl Blockchain {
 
 							// Search by previous hash
 							BlockSearch::SearchByPreviousHash(ref provided_previous_hash) => {
-									if block.previous_hash == *provided_previous_hash {
+									if block.header.previous_hash == *provided_previous_hash {
 											return BlockSearchResult::Success(block);
 									}
 
@@ -138,7 +415,7 @@ impl Blockchain {
 
 							// search by nonce
 							BlockSearch::SearchByNonce(provided_nonce) => {
-									if block.nonce == provided_nonce {
+									if block.header.nonce == provided_nonce {
 											return BlockSearchResult::Success(block);
 									}
 
@@ -149,7 +426,7 @@ impl Blockchain {
 
 							// search by timestamp
 							BlockSearch::SearchByTimestamp(provided_timestamp) => {
-									if block.timestamp == provided_timestamp {
+									if block.header.timestamp == provided_timestamp {
 											return BlockSearchResult::Success(block);
 									}
 
@@ -176,130 +453,523 @@ impl Blockchain {
 			BlockSearchResult::FailOfEmptyBlocks
 	}
 
-	pub fn add_transaction(&mut self, transaction: &Transaction) -> bool {
-			println!(
-					"Sender: {}, Receiver: {}",
-					transaction.sender, transaction.receiver
-			);
-
-			if transaction.sender == transaction.receiver {
-					eprintln!("You cannot send money to yourself");
-					return false;
+	pub fn add_transaction(&mut self, transaction: &Transaction) -> Result<(), TransactionError> {
+			if self.genesis.ledger != LedgerMode::Account {
+					return Err(TransactionError::WrongLedger);
 			}
 
-			let trx_sender_not_miner = transaction.sender != Self::MINING_SENDER;
-
-			if trx_sender_not_miner && !Wallet::verify_transaction(transaction) {
-					println!("Invalid transaction");
+			if address::ledger_key(&transaction.sender) == address::ledger_key(&transaction.receiver) {
+					return Err(TransactionError::SelfTransfer);
+			}
 
-					return false;
+			for address in [&transaction.sender, &transaction.receiver] {
+					address::validate_for(address, self.network())?;
 			}
 
-			// let sender_has_insufficient_funds =
-			//     self.calculate_reward(transaction.clone().sender) < transaction.amount as i64;
+			// mining rewards are only ever created by `mine`
+			if transaction.sender == Self::MINING_SENDER {
+					return Err(TransactionError::MiningRewardSubmitted);
+			}
 
-			// if trx_sender_not_miner && sender_has_insufficient_funds {
-			//     println!("insufficient balance");
+			Wallet::verify_transaction(transaction)?;
 
-			//     return false;
-			// }
+			if transaction.amount.is_zero() {
+					return Err(TransactionError::InvalidAmount);
+			}
 
-			let raw_trx = RawTransaction::new(
-					transaction.sender.as_bytes().to_vec(),
-					transaction.receiver.as_bytes().to_vec(),
-					transaction.amount as f64,
-			);
+			let raw_trx = RawTransaction::from_transaction(transaction)?;
 
 			for existing_tx in self.transaction_pool.iter() {
 					if *existing_tx == raw_trx.serialize() {
-							println!("transaction already exists");
-							return false;
+							return Err(TransactionError::Duplicate);
 					}
 			}
 
+			if transaction.chain_id != self.genesis.chain_id {
+					return Err(TransactionError::WrongChain {
+							expected: self.genesis.chain_id.clone(),
+							found: transaction.chain_id.clone(),
+					});
+			}
+
+			self.ensure_next_nonce(&transaction.sender, transaction.nonce)?;
+			self.ensure_spendable(&transaction.sender, transaction.amount)?;
+
 			self.transaction_pool.push(raw_trx.serialize());
 
-			true
+			Ok(())
 	}
 
-	fn do_proof_of_work(block: &mut Block) -> String {
-			loop {
-					let block_hash = block.hash();
-					let block_hash_as_hex = hex::encode(&block_hash);
+	/// Balance `address` can still spend: its mined balance minus whatever it is
+	/// already sending in transactions waiting in the pool.
+	pub fn spendable_balance(&self, address: &str) -> Amount {
+			if self.genesis.ledger == LedgerMode::Utxo {
+					return self.unspent_outputs(address)
+							.iter()
+							.fold(Amount::ZERO, |total, (_, output)| total.checked_add(output.amount).unwrap_or(Amount::MAX_SUPPLY));
+			}
+
+			let mut available = self.calculate_reward(address.to_string());
+			let key = address::ledger_key(address);
+
+			for tx in self.transaction_pool.iter() {
+					let Ok(raw_trx) = RawTransaction::deserialize(tx) else {
+							continue;
+					};
+
+					if Self::ledger_key(&raw_trx.sender_address) == key {
+							available = available.saturating_sub(raw_trx.value);
+					}
+			}
+
+			available
+	}
+
+	pub fn add_utxo_transaction(&mut self, transaction: &UtxoTransaction) -> Result<(), TransactionError> {
+			if self.genesis.ledger != LedgerMode::Utxo {
+					return Err(TransactionError::WrongLedger);
+			}
+
+			if self.transaction_pool.contains(&transaction.serialize()) {
+					return Err(TransactionError::Duplicate);
+			}
+
+			for output in transaction.outputs.iter() {
+					address::validate_for(&output.address, self.network())?;
+			}
+
+			self.check_utxo_transaction(transaction, &self.utxo_set, &self.pending_spends())?;
+
+			self.transaction_pool.push(transaction.serialize());
+
+			Ok(())
+	}
+
+	/// Mined outputs owned by `address` that no pooled transaction spends yet.
+	pub fn unspent_outputs(&self, address: &str) -> Vec<(OutPoint, TxOutput)> {
+			let pending_spends = self.pending_spends();
+
+			self.utxo_set
+					.unspent_for(address)
+					.into_iter()
+					.filter(|(out_point, _)| !pending_spends.contains(out_point))
+					.collect()
+	}
+
+	fn pending_spends(&self) -> HashSet<OutPoint> {
+			self.get_utxo_transactions()
+					.iter()
+					.flat_map(|transaction| transaction.inputs.iter().map(|input| input.previous_output.clone()))
+					.collect()
+	}
+
+	/// Rules a non-coinbase UTXO transaction has to follow against the outputs in
+	/// `utxo_set`, with `spent` holding outputs already claimed elsewhere.
+	fn check_utxo_transaction(
+			&self,
+			transaction: &UtxoTransaction,
+			utxo_set: &UtxoSet,
+			spent: &HashSet<OutPoint>,
+	) -> Result<(), TransactionError> {
+			if transaction.is_coinbase() {
+					return Err(TransactionError::MiningRewardSubmitted);
+			}
+
+			if transaction.chain_id != self.genesis.chain_id {
+					return Err(TransactionError::WrongChain {
+							expected: self.genesis.chain_id.clone(),
+							found: transaction.chain_id.clone(),
+					});
+			}
+
+			if transaction.outputs.is_empty() || transaction.outputs.iter().any(|output| output.amount.is_zero()) {
+					return Err(TransactionError::InvalidAmount);
+			}
+
+			let mut claimed = HashSet::new();
+			let mut input_total = Amount::ZERO;
+
+			for input in transaction.inputs.iter() {
+					let out_point = &input.previous_output;
+
+					if spent.contains(out_point) || !claimed.insert(out_point.clone()) {
+							return Err(TransactionError::DoubleSpend(out_point.clone()));
+					}
+
+					let output = utxo_set.get(out_point).ok_or_else(|| TransactionError::UnknownOutput(out_point.clone()))?;
+
+					if address::ledger_key(&Wallet::address_of(&input.public_key, self.network())?) != address::ledger_key(&output.address) {
+							return Err(TransactionError::NotOutputOwner(out_point.clone()));
+					}
+
+					Wallet::verify_utxo_input(transaction, input)?;
+
+					input_total = input_total.checked_add(output.amount).ok_or(TransactionError::InvalidAmount)?;
+			}
+
+			let output_total = transaction.output_total().ok_or(TransactionError::InvalidAmount)?;
+
+			if input_total != output_total {
+					return Err(TransactionError::Unbalanced { inputs: input_total, outputs: output_total });
+			}
+
+			Ok(())
+	}
+
+	fn ensure_next_nonce(&self, address: &str, nonce: u64) -> Result<(), TransactionError> {
+			let expected = self.next_nonce(address);
+
+			if nonce < expected {
+					return Err(TransactionError::ReplayedNonce { expected, found: nonce });
+			}
+
+			if nonce > expected {
+					return Err(TransactionError::NonceGap { expected, found: nonce });
+			}
+
+			Ok(())
+	}
+
+	fn ensure_spendable(&self, address: &str, amount: Amount) -> Result<(), TransactionError> {
+			let available = self.spendable_balance(address);
+
+			if available < amount {
+					return Err(TransactionError::InsufficientBalance {
+							address: address.to_string(),
+							available,
+							requested: amount,
+					});
+			}
+
+			Ok(())
+	}
 
-					if (block_hash_as_hex[0..Self::DIFFICULTY]) == "0".repeat(Self::DIFFICULTY) {
-							return block_hash_as_hex;
+	/// Drop pool entries the sender can no longer cover or whose nonce is no
+	/// longer next in line, e.g. after the chain was replaced by a neighbor's,
+	/// so a mined block never contains an overdraft or a replay.
+	fn prune_transaction_pool(&mut self) {
+			let pool = std::mem::take(&mut self.transaction_pool);
+
+			for tx in pool {
+					if let Ok(utxo_trx) = UtxoTransaction::deserialize(&tx) {
+							match self.add_utxo_transaction(&utxo_trx) {
+									Ok(()) => {}
+									Err(err) => warn!("dropping pooled transaction: {}", err),
+							}
+
+							continue;
 					}
 
+					let still_valid = RawTransaction::deserialize(&tx)
+							.and_then(|raw_trx| raw_trx.to_transaction())
+							.map_err(TransactionError::from)
+							.and_then(|transaction| {
+									self.ensure_next_nonce(&transaction.sender, transaction.nonce)?;
+									self.ensure_spendable(&transaction.sender, transaction.amount)
+							});
+
+					match still_valid {
+							Ok(()) => self.transaction_pool.push(tx),
+							Err(err) => warn!("dropping pooled transaction: {}", err),
+					}
+			}
+	}
+
+	fn do_proof_of_work(block: &mut Block) -> String {
+			while !block.header.meets_difficulty() {
 					*block += 1;
 			}
+
+			hex::encode(block.hash())
 	}
 
-	pub fn mine(&mut self) -> bool {
-			let miner_reward_transaction = Transaction {
-					sender: Self::MINING_SENDER.to_string(),
-					receiver: self.address.clone(),
-					amount: Self::MINING_REWARD as f64,
-					public_key: "".into(),
-					signature: "".into(),
+	/// Difficulty of the block following `parents`. It is carried over from
+	/// the parent, except every `retarget_interval` blocks where it moves
+	/// towards the target block time based on the last interval's timestamps.
+	fn expected_difficulty(&self, parents: &[Block]) -> u32 {
+			let schedule = self.genesis.schedule();
+			let parent = &parents[parents.len() - 1].header;
+
+			match schedule.window(parents.len() as u64) {
+					Some(depth) => schedule.retarget(parent, &parents[parents.len() - 1 - depth as usize].header),
+					None => parent.difficulty,
+			}
+	}
+
+	/// Mine the pool into a new block paying the mining reward to this node.
+	/// The block is only kept once it has been written to the store.
+	pub fn mine(&mut self) -> Result<(), BlockchainError> {
+			self.prune_transaction_pool();
+
+			let miner_reward_transaction = match self.genesis.ledger {
+					LedgerMode::Account => RawTransaction::new(
+							Self::MINING_SENDER.as_bytes().to_vec(),
+							self.address.as_bytes().to_vec(),
+							self.genesis.mining_reward,
+							self.chain.len() as u64,
+							self.genesis.chain_id.as_bytes().to_vec(),
+					).serialize(),
+					LedgerMode::Utxo => UtxoTransaction::coinbase(
+							vec![TxOutput { address: self.address.clone(), amount: self.genesis.mining_reward }],
+							self.chain.len() as u64,
+							&self.genesis.chain_id,
+					).serialize(),
 			};
 
-			self.add_transaction(&miner_reward_transaction);
+			self.transaction_pool.push(miner_reward_transaction);
+
+			if let Err(err) = self.create_block(0, self.last_block().unwrap().hash()) {
+					self.transaction_pool.pop();
 
-			self.create_block(0, self.last_block().unwrap().hash());
+					return Err(err);
+			}
+
+			Ok(())
+	}
 
-			true
+	/// Cumulative proof of work of `chain`, the sum of every block's work.
+	pub fn chain_work(chain: &[Block]) -> u128 {
+		chain
+			.iter()
+			.fold(0_u128, |total, block| total.saturating_add(difficulty::work(block.header.difficulty)))
 	}
 
-	pub fn chain_is_valid(chains: &BlocksChain) -> bool {
-		let mut previous_block = chains.get(0).unwrap();
+	/// Fork choice between two chains: the one with more cumulative work is
+	/// greater, equal work is broken by the lower tip hash so every node settles
+	/// on the same fork.
+	pub fn compare_chains(a: &[Block], b: &[Block]) -> Ordering {
+		let tip_hash = |chain: &[Block]| chain.last().map(|block| block.hash()).unwrap_or_default();
+
+		Self::chain_work(a)
+			.cmp(&Self::chain_work(b))
+			.then_with(|| tip_hash(b).cmp(&tip_hash(a)))
+	}
+
+	/// Whether `candidate` wins the fork choice against the local chain. It
+	/// still has to pass `validate_chain` before it may replace it.
+	pub fn is_heavier_chain(&self, candidate: &BlocksChain) -> bool {
+		Self::compare_chains(candidate, &self.chain) == Ordering::Greater
+	}
+
+	pub fn chain_is_valid(&self, chains: &BlocksChain) -> bool {
+		self.validate_chain(chains).is_ok()
+	}
+
+	/// Check linkage, proof of work and every transaction of `chains`, replaying
+	/// balances from the genesis block onwards.
+	pub fn validate_chain(&self, chains: &BlocksChain) -> Result<(), ChainValidationError> {
+		if chains.is_empty() {
+			return Err(ChainValidationError::block(0, BlockValidationError::EmptyChain));
+		}
+
+		if !self.shares_genesis(chains) {
+			return Err(ChainValidationError::block(0, BlockValidationError::GenesisMismatch));
+		}
+
+		let mut balances = HashMap::<LedgerKey, Amount>::new();
+		let mut nonces = HashMap::<LedgerKey, u64>::new();
+		let mut utxo_set = UtxoSet::default();
+		let mut issued = Amount::ZERO;
+
+		for allocation in self.genesis.allocations.iter() {
+			issued = issued.checked_add(allocation.amount)
+				.ok_or(ChainValidationError::block(0, BlockValidationError::SupplyExceeded))?;
+
+			let balance = balances.entry(address::ledger_key(&allocation.address)).or_default();
+			*balance = balance.checked_add(allocation.amount).unwrap();
+		}
+
+		for tx in chains[0].transactions.iter() {
+			if let Ok(coinbase) = UtxoTransaction::deserialize(tx) {
+				utxo_set.apply(&coinbase);
+			}
+		}
+
+		let latest_timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() + BlockHeader::MAX_FUTURE_DRIFT_NANOS;
+
+		for height in 1..chains.len() {
+			let block = &chains[height];
+			let previous_block = &chains[height - 1];
+
+			if block.header.previous_hash != previous_block.hash() {
+				return Err(ChainValidationError::block(height, BlockValidationError::PreviousHashMismatch));
+			}
+
+			if block.header.version != BlockHeader::VERSION {
+				return Err(ChainValidationError::block(height, BlockValidationError::UnsupportedVersion(block.header.version)));
+			}
+
+			if block.header.timestamp <= previous_block.header.timestamp {
+				return Err(ChainValidationError::block(height, BlockValidationError::TimestampNotIncreasing));
+			}
 
-		for current_index in 1..chains.len() {
-			let block = chains.get(current_index).unwrap();
+			if block.header.timestamp > latest_timestamp {
+				return Err(ChainValidationError::block(height, BlockValidationError::TimestampInFuture));
+			}
+
+			let expected_difficulty = self.expected_difficulty(&chains[..height]);
 
-			if block.previous_hash != previous_block.hash() {
-				return false;
+			if block.header.difficulty != expected_difficulty {
+				return Err(ChainValidationError::block(height, BlockValidationError::UnexpectedDifficulty {
+					expected: expected_difficulty,
+					found: block.header.difficulty,
+				}));
 			}
 
-			let block_hash = block.hash();
-			let block_hash_as_str = hex::encode(block_hash);
+			if !block.has_valid_merkle_root() {
+				return Err(ChainValidationError::block(height, BlockValidationError::MerkleRootMismatch));
+			}
 
-			if block_hash_as_str[0..Self::DIFFICULTY] != "0".repeat(Self::DIFFICULTY) {
-				return false;
+			if !block.header.meets_difficulty() {
+				return Err(ChainValidationError::block(height, BlockValidationError::InsufficientProofOfWork));
 			}
 
-			previous_block = block;
+			match self.genesis.ledger {
+				LedgerMode::Account => self.validate_account_block(height, block, &mut balances, &mut nonces, &mut issued)?,
+				LedgerMode::Utxo => self.validate_utxo_block(height, block, &mut utxo_set, &mut issued)?,
+			}
 		}
 
-		true
+		Ok(())
 	}
 
-	pub fn calculate_reward(&self, address: String) -> f64 {
-		let mut total_amount: f64 = 0.0;
+	fn validate_account_block(
+		&self,
+		height: usize,
+		block: &Block,
+		balances: &mut HashMap<LedgerKey, Amount>,
+		nonces: &mut HashMap<LedgerKey, u64>,
+		issued: &mut Amount,
+	) -> Result<(), ChainValidationError> {
+		let mut mining_rewards = 0;
+
+		for (idx, tx) in block.transactions.iter().enumerate() {
+			let invalid_tx = |reason| ChainValidationError::transaction(height, idx, reason);
 
-		for i in 0..self.chain.len() {
-			let block = &self[i];
+			let transaction = RawTransaction::deserialize(tx)
+				.and_then(|raw_trx| raw_trx.to_transaction())
+				.map_err(|err| invalid_tx(BlockValidationError::MalformedTransaction(err)))?;
 
-			for tx in block.transactions.iter() {
-				let deserialized_tx = RawTransaction::deserialize(tx.clone());
+			if transaction.sender == Self::MINING_SENDER {
+				mining_rewards += 1;
 
-				let tx_value = deserialized_tx.value;
+				if transaction.amount != self.genesis.mining_reward {
+					return Err(invalid_tx(BlockValidationError::InvalidMiningReward { amount: transaction.amount }));
+				}
 
-				if <String as Into<Vec<u8>>>::into(address.clone())
-					== deserialized_tx.recipient_address
-				{
-					total_amount += tx_value;
+				*issued = issued.checked_add(transaction.amount)
+					.ok_or_else(|| invalid_tx(BlockValidationError::SupplyExceeded))?;
+
+				address::validate_for(&transaction.receiver, self.network())
+					.map_err(|err| invalid_tx(BlockValidationError::Rejected(err.into())))?;
+			} else {
+				// the same checks `add_transaction` runs on client submissions
+				if address::ledger_key(&transaction.sender) == address::ledger_key(&transaction.receiver) {
+					return Err(invalid_tx(BlockValidationError::Rejected(TransactionError::SelfTransfer)));
 				}
 
-				if <String as Into<Vec<u8>>>::into(address.clone())
-					== deserialized_tx.sender_address
-				{
-					total_amount -= tx_value;
+				for address in [&transaction.sender, &transaction.receiver] {
+					address::validate_for(address, self.network())
+						.map_err(|err| invalid_tx(BlockValidationError::Rejected(err.into())))?;
 				}
+
+				Wallet::verify_transaction(&transaction)
+					.map_err(|err| invalid_tx(BlockValidationError::Rejected(err)))?;
+
+				if transaction.chain_id != self.genesis.chain_id {
+					return Err(invalid_tx(BlockValidationError::Rejected(TransactionError::WrongChain {
+						expected: self.genesis.chain_id.clone(),
+						found: transaction.chain_id.clone(),
+					})));
+				}
+
+				let expected_nonce = nonces.entry(address::ledger_key(&transaction.sender)).or_default();
+
+				let (expected, found) = (*expected_nonce, transaction.nonce);
+
+				if found < expected {
+					return Err(invalid_tx(BlockValidationError::Rejected(TransactionError::ReplayedNonce { expected, found })));
+				}
+
+				if found > expected {
+					return Err(invalid_tx(BlockValidationError::Rejected(TransactionError::NonceGap { expected, found })));
+				}
+
+				*expected_nonce += 1;
+
+				let sender_balance = balances.entry(address::ledger_key(&transaction.sender)).or_default();
+
+				*sender_balance = sender_balance.checked_sub(transaction.amount).ok_or_else(|| {
+					invalid_tx(BlockValidationError::Rejected(TransactionError::InsufficientBalance {
+						address: transaction.sender.clone(),
+						available: *sender_balance,
+						requested: transaction.amount,
+					}))
+				})?;
+			}
+
+			// balances never outgrow the supply issued so far, so this cannot overflow
+			let receiver_balance = balances.entry(address::ledger_key(&transaction.receiver)).or_default();
+			*receiver_balance = receiver_balance.checked_add(transaction.amount)
+				.ok_or_else(|| invalid_tx(BlockValidationError::SupplyExceeded))?;
+		}
+
+		if mining_rewards != 1 {
+			return Err(ChainValidationError::block(height, BlockValidationError::MiningRewardCount(mining_rewards)));
+		}
+
+		Ok(())
+	}
+
+	fn validate_utxo_block(
+		&self,
+		height: usize,
+		block: &Block,
+		utxo_set: &mut UtxoSet,
+		issued: &mut Amount,
+	) -> Result<(), ChainValidationError> {
+		let mut mining_rewards = 0;
+
+		for (idx, tx) in block.transactions.iter().enumerate() {
+			let invalid_tx = |reason| ChainValidationError::transaction(height, idx, reason);
+
+			let transaction = UtxoTransaction::deserialize(tx)
+				.map_err(|err| invalid_tx(BlockValidationError::MalformedTransaction(err)))?;
+
+			if transaction.is_coinbase() {
+				mining_rewards += 1;
+
+				let amount = transaction.output_total().unwrap_or(Amount::MAX_SUPPLY);
+				let is_valid_reward = amount == self.genesis.mining_reward && transaction.height == height as u64;
+
+				if !is_valid_reward {
+					return Err(invalid_tx(BlockValidationError::InvalidMiningReward { amount }));
+				}
+
+				*issued = issued.checked_add(amount)
+					.ok_or_else(|| invalid_tx(BlockValidationError::SupplyExceeded))?;
+			} else {
+				self.check_utxo_transaction(&transaction, utxo_set, &HashSet::new())
+					.map_err(|err| invalid_tx(BlockValidationError::Rejected(err)))?;
 			}
+
+			utxo_set.apply(&transaction);
+		}
+
+		if mining_rewards != 1 {
+			return Err(ChainValidationError::block(height, BlockValidationError::MiningRewardCount(mining_rewards)));
+		}
+
+		Ok(())
+	}
+
+	pub fn calculate_reward(&self, address: String) -> Amount {
+		// both ledgers keep balances up to date as blocks are recorded
+		if self.genesis.ledger == LedgerMode::Utxo {
+			return self.utxo_set.balance(&address);
 		}
 
-		total_amount
+		self.balances.get(&address::ledger_key(&address)).copied().unwrap_or(Amount::ZERO)
 	}
 }
 
@@ -310,3 +980,382 @@ impl Index<usize> for Blockchain {
 			self.chain.get(index).unwrap()
 	}
 }
+
+
+#[cfg(test)]
+mod test {
+	use std::cmp::Ordering;
+
+	use super::Blockchain;
+	use crate::{
+		core::{
+			amount::Amount,
+			block::{Block, BlockHeader},
+			block_tree::BlockAcceptance,
+			error::{AddressError, BlockValidationError, ChainValidationError, TransactionError},
+			genesis::{GenesisAllocation, GenesisSpec},
+			network::Network,
+			raw_transaction::RawTransaction,
+			transaction::Transaction,
+			utxo::{select_coins, LedgerMode, TxOutput},
+			wallet::Wallet,
+		},
+		utils::{address::AddressFormat, serializable::Serializable},
+	};
+
+	fn coins(coins: u64) -> Amount {
+		Amount::from_coins(coins).unwrap()
+	}
+
+	fn transfer(blockchain: &Blockchain, sender: &Wallet, amount: Amount) -> Transaction {
+		let nonce = blockchain.next_nonce(&sender.address());
+
+		sender.sign_transaction(Wallet::default().address(), amount, nonce, blockchain.chain_id())
+	}
+
+	fn funded_blockchain(wallet: &Wallet, amount: Amount) -> Blockchain {
+		let genesis = GenesisSpec {
+			difficulty: 1,
+			allocations: vec![GenesisAllocation { address: wallet.address(), amount }],
+			..GenesisSpec::default()
+		};
+
+		Blockchain::with_genesis(Wallet::default().address(), genesis)
+	}
+
+	#[test]
+	fn test_validate_chain_replays_transactions() {
+		let alice = Wallet::default();
+		let mut blockchain = funded_blockchain(&alice, coins(10));
+
+		assert_eq!(blockchain.add_transaction(&transfer(&blockchain, &alice, coins(4))), Ok(()));
+		blockchain.mine().unwrap();
+		assert_eq!(blockchain.validate_chain(&blockchain.chain), Ok(()));
+
+		// sneak an overdraft past `add_transaction` straight into the pool
+		let overdraft = transfer(&blockchain, &alice, coins(7));
+		blockchain.transaction_pool.push(RawTransaction::from_transaction(&overdraft).unwrap().serialize());
+		blockchain.create_block(0, blockchain.last_block().unwrap().hash()).unwrap();
+
+		let err = blockchain.validate_chain(&blockchain.chain).unwrap_err();
+		assert_eq!((err.height, err.transaction), (2, Some(0)));
+		assert!(matches!(
+			err.reason,
+			BlockValidationError::Rejected(TransactionError::InsufficientBalance { .. })
+		));
+	}
+
+	#[test]
+	fn test_validate_chain_rejects_forged_transactions() {
+		let alice = Wallet::default();
+		let mut blockchain = funded_blockchain(&alice, coins(10));
+
+		assert_eq!(blockchain.add_transaction(&transfer(&blockchain, &alice, coins(1))), Ok(()));
+		blockchain.mine().unwrap();
+
+		let mut chain = blockchain.chain.clone();
+		let mut forged = RawTransaction::deserialize(&chain[1].transactions[0]).unwrap();
+		forged.value = coins(9);
+		chain[1].transactions[0] = forged.serialize();
+		chain[1].update_merkle_root();
+		Blockchain::do_proof_of_work(&mut chain[1]);
+
+		let err = blockchain.validate_chain(&chain).unwrap_err();
+		assert_eq!((err.height, err.transaction), (1, Some(0)));
+		assert_eq!(err.reason, BlockValidationError::Rejected(TransactionError::InvalidSignature));
+	}
+
+	#[test]
+	fn test_validate_chain_rejects_foreign_network_receivers() {
+		let alice = Wallet::default();
+		let mut blockchain = funded_blockchain(&alice, coins(10));
+
+		// a peer block paying a testnet address that `add_transaction` would refuse
+		let nonce = blockchain.next_nonce(&alice.address());
+		let foreign = alice.sign_transaction(Wallet::new(Network::Testnet).address(), coins(1), nonce, blockchain.chain_id());
+		blockchain.transaction_pool.push(RawTransaction::from_transaction(&foreign).unwrap().serialize());
+		blockchain.create_block(0, blockchain.last_block().unwrap().hash()).unwrap();
+
+		let err = blockchain.validate_chain(&blockchain.chain).unwrap_err();
+		assert_eq!((err.height, err.transaction), (1, Some(0)));
+		assert!(matches!(
+			err.reason,
+			BlockValidationError::Rejected(TransactionError::InvalidAddress(AddressError::WrongNetwork { found: Network::Testnet, .. }))
+		));
+	}
+
+	#[test]
+	fn test_validate_chain_rejects_future_timestamps() {
+		let alice = Wallet::default();
+		let mut blockchain = funded_blockchain(&alice, coins(10));
+		blockchain.mine().unwrap();
+
+		let mut chain = blockchain.chain.clone();
+		chain[1].header.timestamp += BlockHeader::MAX_FUTURE_DRIFT_NANOS / 2;
+		Blockchain::do_proof_of_work(&mut chain[1]);
+		assert_eq!(blockchain.validate_chain(&chain), Ok(()));
+
+		chain[1].header.timestamp += BlockHeader::MAX_FUTURE_DRIFT_NANOS;
+		Blockchain::do_proof_of_work(&mut chain[1]);
+
+		let err = blockchain.validate_chain(&chain).unwrap_err();
+		assert_eq!((err.height, err.reason), (1, BlockValidationError::TimestampInFuture));
+	}
+
+	#[test]
+	fn test_pending_transactions_count_against_balance() {
+		let alice = Wallet::default();
+		let mut blockchain = funded_blockchain(&alice, coins(10));
+
+		assert_eq!(blockchain.add_transaction(&transfer(&blockchain, &alice, coins(6))), Ok(()));
+		assert_eq!(blockchain.spendable_balance(&alice.address()), coins(4));
+
+		let err = blockchain.add_transaction(&transfer(&blockchain, &alice, coins(5))).unwrap_err();
+		assert!(matches!(
+			err,
+			TransactionError::InsufficientBalance { available, requested, .. } if available == coins(4) && requested == coins(5)
+		));
+	}
+
+	#[test]
+	fn test_mined_transactions_cannot_be_replayed() {
+		let alice = Wallet::default();
+		let mut blockchain = funded_blockchain(&alice, coins(10));
+
+		let transaction = transfer(&blockchain, &alice, coins(1));
+		assert_eq!(blockchain.add_transaction(&transaction), Ok(()));
+		blockchain.mine().unwrap();
+
+		assert_eq!(blockchain.next_nonce(&alice.address()), 1);
+		assert_eq!(
+			blockchain.add_transaction(&transaction),
+			Err(TransactionError::ReplayedNonce { expected: 1, found: 0 })
+		);
+
+		let skipped = alice.sign_transaction(Wallet::default().address(), coins(1), 5, blockchain.chain_id());
+		assert_eq!(blockchain.add_transaction(&skipped), Err(TransactionError::NonceGap { expected: 1, found: 5 }));
+
+		let other_chain = alice.sign_transaction(Wallet::default().address(), coins(1), 1, "elsewhere");
+		assert!(matches!(blockchain.add_transaction(&other_chain), Err(TransactionError::WrongChain { .. })));
+
+		let other_network = alice.sign_transaction(Wallet::new(Network::Testnet).address(), coins(1), 1, blockchain.chain_id());
+		assert!(matches!(
+			blockchain.add_transaction(&other_network),
+			Err(TransactionError::InvalidAddress(AddressError::WrongNetwork { found: Network::Testnet, .. }))
+		));
+	}
+
+	#[test]
+	fn test_address_formats_share_balances() {
+		let alice = Wallet::default();
+		let bob = Wallet::default();
+		let mut blockchain = funded_blockchain(&alice, coins(10));
+
+		let to_bech32 = alice.sign_transaction(bob.address_in(AddressFormat::Bech32), coins(3), 0, blockchain.chain_id());
+		assert_eq!(blockchain.add_transaction(&to_bech32), Ok(()));
+
+		let details = alice.get_details();
+		let alice_bech32 = Wallet::new_from(&details.public_key, &details.private_key, &details.bech32_address).unwrap();
+		let from_bech32 = alice_bech32.sign_transaction(bob.address(), coins(2), 1, blockchain.chain_id());
+		assert_eq!(blockchain.add_transaction(&from_bech32), Ok(()));
+		blockchain.mine().unwrap();
+
+		assert_eq!(blockchain.calculate_reward(bob.address()), coins(5));
+		assert_eq!(blockchain.calculate_reward(bob.address_in(AddressFormat::Bech32)), coins(5));
+		assert_eq!(blockchain.next_nonce(&details.bech32_address), 2);
+		assert!(blockchain.chain_is_valid(&blockchain.chain));
+	}
+
+	#[test]
+	fn test_utxo_spends_return_change() {
+		let alice = Wallet::default();
+		let bob = Wallet::default();
+		let genesis = GenesisSpec {
+			difficulty: 1,
+			ledger: LedgerMode::Utxo,
+			allocations: vec![GenesisAllocation { address: alice.address(), amount: coins(10) }],
+			..GenesisSpec::default()
+		};
+		let mut blockchain = Blockchain::with_genesis(Wallet::default().address(), genesis);
+
+		let unspent = blockchain.unspent_outputs(&alice.address());
+		let (inputs, change) = select_coins(&unspent, coins(4)).unwrap();
+		let outputs = vec![
+			TxOutput { address: bob.address(), amount: coins(4) },
+			TxOutput { address: alice.address(), amount: change },
+		];
+		let spend = alice.sign_utxo_transaction(inputs.clone(), outputs, blockchain.chain_id());
+
+		assert_eq!(blockchain.add_utxo_transaction(&spend), Ok(()));
+		assert!(blockchain.unspent_outputs(&alice.address()).is_empty());
+
+		let double_spend = alice.sign_utxo_transaction(
+			inputs.clone(),
+			vec![TxOutput { address: bob.address(), amount: coins(10) }],
+			blockchain.chain_id(),
+		);
+		assert_eq!(
+			blockchain.add_utxo_transaction(&double_spend),
+			Err(TransactionError::DoubleSpend(inputs[0].clone()))
+		);
+
+		blockchain.mine().unwrap();
+		assert_eq!(blockchain.validate_chain(&blockchain.chain), Ok(()));
+		assert_eq!(blockchain.calculate_reward(alice.address()), coins(6));
+		assert_eq!(blockchain.calculate_reward(bob.address()), coins(4));
+		assert_eq!(
+			blockchain.add_utxo_transaction(&double_spend),
+			Err(TransactionError::UnknownOutput(inputs[0].clone()))
+		);
+	}
+
+	#[test]
+	fn test_difficulty_retargets_towards_block_time() {
+		let genesis = GenesisSpec {
+			difficulty: 1,
+			retarget_interval: 2,
+			// blocks come in far faster than this, so difficulty has to go up
+			target_block_time_ms: 60_000,
+			..GenesisSpec::default()
+		};
+		let mut blockchain = Blockchain::with_genesis(Wallet::default().address(), genesis);
+
+		for _ in 0..5 {
+			blockchain.mine().unwrap();
+		}
+
+		let difficulties: Vec<u32> = blockchain.chain.iter().map(|block| block.header.difficulty).collect();
+		assert_eq!(difficulties, vec![1, 1, 1, 1, 2, 2]);
+		assert_eq!(blockchain.validate_chain(&blockchain.chain), Ok(()));
+
+		let mut chain = blockchain.chain.clone();
+		chain[4].header.difficulty = 1;
+		Blockchain::do_proof_of_work(&mut chain[4]);
+
+		let err = blockchain.validate_chain(&chain).unwrap_err();
+		assert_eq!(err, ChainValidationError::block(4, BlockValidationError::UnexpectedDifficulty { expected: 2, found: 1 }));
+	}
+
+	fn fork_block(parent: &Block, difficulty: u32) -> Block {
+		let mut block = Block::new(0, parent.hash());
+		block.header.difficulty = difficulty;
+		block.update_merkle_root();
+		Blockchain::do_proof_of_work(&mut block);
+
+		block
+	}
+
+	#[test]
+	fn test_heavier_fork_beats_longer_fork() {
+		let blockchain = Blockchain::with_genesis(Wallet::default().address(), GenesisSpec { difficulty: 1, ..GenesisSpec::default() });
+		let genesis = blockchain.chain[0].clone();
+
+		let mut long_fork = vec![genesis.clone()];
+		for _ in 0..3 {
+			let block = fork_block(long_fork.last().unwrap(), 1);
+			long_fork.push(block);
+		}
+
+		let heavy_fork = vec![genesis.clone(), fork_block(&genesis, 4)];
+
+		assert!(long_fork.len() > heavy_fork.len());
+		assert_eq!(Blockchain::compare_chains(&heavy_fork, &long_fork), Ordering::Greater);
+		assert_eq!(Blockchain::compare_chains(&long_fork, &heavy_fork), Ordering::Less);
+		assert!(blockchain.is_heavier_chain(&heavy_fork));
+		assert!(!blockchain.is_heavier_chain(&blockchain.chain));
+	}
+
+	#[test]
+	fn test_equal_work_prefers_lower_tip_hash() {
+		let blockchain = Blockchain::with_genesis(Wallet::default().address(), GenesisSpec { difficulty: 1, ..GenesisSpec::default() });
+		let genesis = blockchain.chain[0].clone();
+
+		let first = vec![genesis.clone(), fork_block(&genesis, 2)];
+		let mut second = vec![genesis.clone(), fork_block(&genesis, 1)];
+		second.push(fork_block(&second[1], 1));
+
+		assert_eq!(Blockchain::chain_work(&first), Blockchain::chain_work(&second));
+
+		let (lower, higher) = if first[1].hash() < second[2].hash() { (&first, &second) } else { (&second, &first) };
+
+		assert_eq!(Blockchain::compare_chains(lower, higher), Ordering::Greater);
+		assert_eq!(Blockchain::compare_chains(higher, lower), Ordering::Less);
+		assert_eq!(Blockchain::compare_chains(lower, lower), Ordering::Equal);
+	}
+
+	#[test]
+	fn test_reorg_returns_orphaned_transactions_to_pool() {
+		let alice = Wallet::default();
+		let mut local = funded_blockchain(&alice, coins(10));
+		let mut other = funded_blockchain(&alice, coins(10));
+
+		let first = transfer(&local, &alice, coins(1));
+		assert_eq!(local.add_transaction(&first), Ok(()));
+		let second = transfer(&local, &alice, coins(1));
+		assert_eq!(local.add_transaction(&second), Ok(()));
+		local.mine().unwrap();
+
+		let third = transfer(&local, &alice, coins(1));
+		assert_eq!(local.add_transaction(&third), Ok(()));
+
+		// the other branch only picked up the first transaction, but is heavier
+		assert_eq!(other.add_transaction(&first), Ok(()));
+		other.mine().unwrap();
+		other.mine().unwrap();
+		assert!(local.is_heavier_chain(&other.chain));
+
+		let old_tip = local.last_block().unwrap().hash();
+		let event = local.replace_chain(other.chain.clone()).unwrap();
+
+		assert_eq!((event.common_ancestor, event.depth, event.reinjected), (0, 1, 1));
+		assert_eq!(event.old_tip, old_tip);
+		assert_eq!(event.new_tip, other.last_block().unwrap().hash());
+
+		let pool: Vec<Transaction> = local.get_transactions();
+		assert_eq!(pool.len(), 2);
+		assert_eq!((pool[0].nonce, pool[1].nonce), (second.nonce, third.nonce));
+		assert_eq!(local.next_nonce(&alice.address()), 3);
+	}
+
+	#[test]
+	fn test_received_blocks_extend_chain_out_of_order() {
+		let alice = Wallet::default();
+		let mut local = funded_blockchain(&alice, coins(10));
+		let mut other = funded_blockchain(&alice, coins(10));
+
+		other.mine().unwrap();
+		other.mine().unwrap();
+
+		assert_eq!(local.receive_block(other.chain[2].clone()), Ok(BlockAcceptance::Orphaned));
+		assert_eq!(local.chain.len(), 1);
+
+		let accepted = local.receive_block(other.chain[1].clone()).unwrap();
+		assert!(matches!(accepted, BlockAcceptance::Accepted(ref event) if event.depth == 0));
+		assert_eq!(local.chain, other.chain);
+		assert_eq!(local.tips().len(), 1);
+		assert_eq!(local.receive_block(other.chain[1].clone()), Ok(BlockAcceptance::Known));
+	}
+
+	#[test]
+	fn test_invalid_heaviest_branch_falls_back_to_next_valid_one() {
+		let alice = Wallet::default();
+		let mut local = funded_blockchain(&alice, coins(10));
+		let mut other = funded_blockchain(&alice, coins(10));
+
+		other.mine().unwrap();
+		other.mine().unwrap();
+
+		// two blocks without a mining reward outweigh the single valid child
+		let invalid = fork_block(&other.chain[1], 1);
+		let invalid_child = fork_block(&invalid, 1);
+
+		assert_eq!(local.receive_block(other.chain[2].clone()), Ok(BlockAcceptance::Orphaned));
+		assert_eq!(local.receive_block(invalid_child.clone()), Ok(BlockAcceptance::Orphaned));
+		assert_eq!(local.receive_block(invalid.clone()), Ok(BlockAcceptance::Orphaned));
+
+		let accepted = local.receive_block(other.chain[1].clone()).unwrap();
+		assert!(matches!(accepted, BlockAcceptance::Accepted(_)));
+		assert_eq!(local.chain, other.chain);
+		assert_eq!(local.tips().len(), 1);
+	}
+}
@@ -0,0 +1,87 @@
+use super::block::BlockHeader;
+
+/// Difficulty is the number of leading zero bits a block hash needs, so every
+/// extra bit doubles the expected work.
+pub const MAX_DIFFICULTY: u32 = 256;
+pub const MIN_DIFFICULTY: u32 = 1;
+
+pub fn leading_zero_bits(hash: &[u8]) -> u32 {
+  let mut bits = 0;
+
+  for byte in hash.iter() {
+    bits += byte.leading_zeros();
+
+    if *byte != 0 {
+      break;
+    }
+  }
+
+  bits
+}
+
+pub fn meets_difficulty(hash: &[u8], difficulty: u32) -> bool {
+  leading_zero_bits(hash) >= difficulty
+}
+
+/// Expected number of hashes needed to meet `difficulty`, saturating at
+/// `u128::MAX` for difficulties no chain will ever reach.
+pub fn work(difficulty: u32) -> u128 {
+  1_u128.checked_shl(difficulty).unwrap_or(u128::MAX)
+}
+
+/// Difficulty for the next retarget window given how long the last one took,
+/// moving by at most one bit (a factor of two) at a time.
+pub fn retarget(difficulty: u32, actual_time: u128, expected_time: u128) -> u32 {
+  if actual_time.saturating_mul(2) < expected_time {
+    return (difficulty + 1).min(MAX_DIFFICULTY);
+  }
+
+  if actual_time > expected_time.saturating_mul(2) {
+    return difficulty.saturating_sub(1).max(MIN_DIFFICULTY);
+  }
+
+  difficulty
+}
+
+/// Retarget parameters of a chain, taken from its `GenesisSpec`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+  pub target_block_time_ms: u64,
+  pub retarget_interval: u64,
+}
+
+impl Schedule {
+  /// Number of blocks below the parent that open the window the block at
+  /// `height` is retargeted on, `None` when it keeps its parent's difficulty.
+  pub fn window(&self, height: u64) -> Option<u64> {
+    let interval = self.retarget_interval.max(1);
+
+    // the genesis timestamp is fixed, so the first interval is not measured
+    (height.is_multiple_of(interval) && height > interval).then_some(interval)
+  }
+
+  /// Difficulty following `parent` for a window opened by `first`.
+  pub fn retarget(&self, parent: &BlockHeader, first: &BlockHeader) -> u32 {
+    let actual_time = parent.timestamp.saturating_sub(first.timestamp);
+    let expected_time = self.retarget_interval as u128 * self.target_block_time_ms as u128 * 1_000_000;
+
+    retarget(parent.difficulty, actual_time, expected_time)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{leading_zero_bits, retarget, MIN_DIFFICULTY};
+
+  #[test]
+  fn test_leading_zero_bits_and_retarget() {
+    assert_eq!(leading_zero_bits(&[0x00, 0x0f, 0xff]), 12);
+    assert_eq!(leading_zero_bits(&[0x80]), 0);
+    assert_eq!(leading_zero_bits(&[0x00, 0x00]), 16);
+
+    assert_eq!(retarget(16, 100, 1_000), 17);
+    assert_eq!(retarget(16, 3_000, 1_000), 15);
+    assert_eq!(retarget(16, 1_500, 1_000), 16);
+    assert_eq!(retarget(MIN_DIFFICULTY, 3_000, 1_000), MIN_DIFFICULTY);
+  }
+}
@@ -0,0 +1,220 @@
+use std::io;
+
+use thiserror::Error;
+
+use super::{amount::Amount, network::Network, utxo::OutPoint};
+
+/// Any error returned by the public API of this crate.
+#[derive(Debug, Error)]
+pub enum BlockchainError {
+  #[error(transparent)]
+  Transaction(#[from] TransactionError),
+  #[error(transparent)]
+  Chain(#[from] ChainValidationError),
+  #[error(transparent)]
+  Block(#[from] ReceiveBlockError),
+  #[error(transparent)]
+  Key(#[from] KeyError),
+  #[error(transparent)]
+  Decode(#[from] DecodeError),
+  #[error(transparent)]
+  Address(#[from] AddressError),
+  #[error(transparent)]
+  Amount(#[from] AmountError),
+  #[error(transparent)]
+  Keystore(#[from] KeystoreError),
+  #[error("storage error: {0}")]
+  Storage(#[from] io::Error),
+}
+
+/// Reason a decimal string could not be turned into an `Amount`.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum AmountError {
+  #[error("amount is empty")]
+  Empty,
+  #[error("{0:?} is not a valid amount")]
+  Invalid(String),
+  #[error("{0:?} has more than {decimals} decimal places", decimals = Amount::DECIMALS)]
+  TooPrecise(String),
+  #[error("{0:?} exceeds the maximum supply")]
+  ExceedsMaxSupply(String),
+}
+
+/// Reason a hex encoded key could not be used.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum KeyError {
+  #[error("key is not valid hex")]
+  InvalidHex,
+  #[error("public key is not a point on the curve")]
+  InvalidPublicKey,
+  #[error("private key is not a valid scalar")]
+  InvalidPrivateKey,
+  #[error("mnemonic is not a valid BIP39 phrase")]
+  InvalidMnemonic,
+  #[error("{0:?} is not a valid derivation path")]
+  InvalidDerivationPath(String),
+  #[error("address index {0} is reserved for hardened derivation")]
+  HardenedIndex(u32),
+}
+
+/// Reason a keystore could not be decrypted.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum KeystoreError {
+  #[error("keystore version {0} is not supported")]
+  UnsupportedVersion(u32),
+  #[error("keystore cipher {0:?} is not supported")]
+  UnsupportedCipher(String),
+  #[error("keystore kdf {0:?} is not supported")]
+  UnsupportedKdf(String),
+  #[error("keystore kdf parameters are invalid")]
+  InvalidKdfParams,
+  #[error("keystore field {0} is malformed")]
+  Malformed(&'static str),
+  /// the authentication tag did not match, either the password is wrong or
+  /// the file was modified
+  #[error("wrong password or corrupted keystore")]
+  InvalidPassword,
+}
+
+/// Name that is not one of the `Network` variants.
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("unknown network {0:?}, expected mainnet, testnet or regtest")]
+pub struct UnknownNetworkError(pub String);
+
+/// Reason a string is not a valid address in any supported format.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum AddressError {
+  #[error("address is not valid base58")]
+  InvalidBase58,
+  #[error("address is not valid bech32")]
+  InvalidBech32,
+  #[error("address decodes to an unexpected {0} bytes")]
+  InvalidLength(usize),
+  #[error("address has unsupported version {0}")]
+  UnsupportedVersion(u8),
+  #[error("address checksum does not match")]
+  ChecksumMismatch,
+  #[error("address belongs to {found}, expected {expected}")]
+  WrongNetwork { expected: Network, found: Network },
+}
+
+/// Reason a binary record could not be decoded.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum DecodeError {
+  #[error("record ends early")]
+  UnexpectedEnd,
+  #[error("record has unsupported version {0}")]
+  UnsupportedVersion(u8),
+  #[error("field of {0} bytes exceeds the length limit")]
+  FieldTooLong(u64),
+  #[error("record has trailing bytes")]
+  TrailingBytes,
+  #[error("field {0} is malformed")]
+  InvalidField(&'static str),
+}
+
+/// Reason a transaction was refused by `Blockchain::add_transaction`.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum TransactionError {
+  #[error("you cannot send money to yourself")]
+  SelfTransfer,
+  #[error("mining rewards cannot be submitted as transactions")]
+  MiningRewardSubmitted,
+  #[error("transaction signature is invalid")]
+  InvalidSignature,
+  #[error("transaction is malformed: {0}")]
+  InvalidEncoding(#[from] DecodeError),
+  #[error("transaction key is invalid: {0}")]
+  InvalidKey(#[from] KeyError),
+  #[error("transaction address is invalid: {0}")]
+  InvalidAddress(#[from] AddressError),
+  #[error("public key does not belong to the sender address")]
+  SenderMismatch,
+  #[error("transaction amount must be greater than zero")]
+  InvalidAmount,
+  #[error("insufficient balance for {address}: {available} available, {requested} requested")]
+  InsufficientBalance { address: String, available: Amount, requested: Amount },
+  #[error("transaction already exists")]
+  Duplicate,
+  #[error("transaction is for chain {found:?}, expected {expected:?}")]
+  WrongChain { expected: String, found: String },
+  #[error("nonce {found} was already used, next nonce is {expected}")]
+  ReplayedNonce { expected: u64, found: u64 },
+  #[error("nonce {found} skips ahead of next nonce {expected}")]
+  NonceGap { expected: u64, found: u64 },
+  #[error("transaction does not match the ledger mode of this chain")]
+  WrongLedger,
+  #[error("output {}:{} does not exist or is already spent", .0.txid, .0.index)]
+  UnknownOutput(OutPoint),
+  #[error("output {}:{} is spent more than once", .0.txid, .0.index)]
+  DoubleSpend(OutPoint),
+  #[error("input key does not own output {}:{}", .0.txid, .0.index)]
+  NotOutputOwner(OutPoint),
+  #[error("inputs of {inputs} do not match outputs of {outputs}")]
+  Unbalanced { inputs: Amount, outputs: Amount },
+}
+
+/// Reason a block was refused while validating a chain.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum BlockValidationError {
+  #[error("chain has no blocks")]
+  EmptyChain,
+  #[error("genesis block does not match")]
+  GenesisMismatch,
+  #[error("previous hash does not match the parent block")]
+  PreviousHashMismatch,
+  #[error("block hash does not meet the difficulty")]
+  InsufficientProofOfWork,
+  #[error("block difficulty is {found} bits, expected {expected} bits")]
+  UnexpectedDifficulty { expected: u32, found: u32 },
+  #[error("block timestamp is not after its parent's")]
+  TimestampNotIncreasing,
+  #[error("block timestamp is too far in the future")]
+  TimestampInFuture,
+  #[error("block version {0} is not supported")]
+  UnsupportedVersion(u32),
+  #[error("merkle root does not match the transactions")]
+  MerkleRootMismatch,
+  #[error("transaction could not be decoded: {0}")]
+  MalformedTransaction(DecodeError),
+  #[error(transparent)]
+  Rejected(TransactionError),
+  #[error("mining reward of {amount} is not allowed")]
+  InvalidMiningReward { amount: Amount },
+  #[error("block has {0} mining rewards, expected exactly one")]
+  MiningRewardCount(usize),
+  #[error("balances exceed the maximum supply of {max}", max = Amount::MAX_SUPPLY)]
+  SupplyExceeded,
+}
+
+/// First invalid block (and transaction, if any) found in a chain.
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("invalid {}block {height}: {reason}", .transaction.map(|index| format!("transaction {} in ", index)).unwrap_or_default())]
+pub struct ChainValidationError {
+  pub height: usize,
+  pub transaction: Option<usize>,
+  pub reason: BlockValidationError,
+}
+
+/// Reason a block announced by a peer was not taken by `Blockchain::receive_block`.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum ReceiveBlockError {
+  /// the block itself is malformed
+  #[error("block rejected: {0}")]
+  Rejected(BlockValidationError),
+  /// the branch the block completes breaks the consensus rules
+  #[error(transparent)]
+  Invalid(ChainValidationError),
+  #[error("failed to store block: {0}")]
+  Storage(String),
+}
+
+impl ChainValidationError {
+  pub fn block(height: usize, reason: BlockValidationError) -> Self {
+    Self { height, transaction: None, reason }
+  }
+
+  pub fn transaction(height: usize, transaction: usize, reason: BlockValidationError) -> Self {
+    Self { height, transaction: Some(transaction), reason }
+  }
+}
@@ -0,0 +1,169 @@
+use std::{fs, io::{self, ErrorKind}, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::serializable::Serializable;
+
+use super::{
+  amount::Amount,
+  block::Block,
+  blockchain::Blockchain,
+  difficulty::Schedule,
+  network::Network,
+  raw_transaction::RawTransaction,
+  utxo::{LedgerMode, TxOutput, UtxoTransaction},
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenesisAllocation {
+  pub address: String,
+  pub amount: Amount,
+}
+
+/// Everything a node needs to build the very first block of its chain.
+///
+/// Nodes only agree on a chain when they share the same spec, so the genesis
+/// block is derived from it deterministically instead of from the clock.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenesisSpec {
+  /// nanoseconds since the unix epoch, like `BlockHeader::timestamp`
+  pub timestamp: u128,
+  #[serde(default)]
+  pub allocations: Vec<GenesisAllocation>,
+  /// leading zero bits the first blocks need, see `difficulty`
+  pub difficulty: u32,
+  /// block time in milliseconds the difficulty is retargeted towards
+  #[serde(default = "GenesisSpec::default_target_block_time_ms")]
+  pub target_block_time_ms: u64,
+  /// number of blocks between two difficulty retargets
+  #[serde(default = "GenesisSpec::default_retarget_interval")]
+  pub retarget_interval: u64,
+  pub mining_reward: Amount,
+  /// signed into every transaction so it cannot be replayed on another chain
+  pub chain_id: String,
+  #[serde(default)]
+  pub ledger: LedgerMode,
+  /// network whose addresses the chain accepts
+  #[serde(default)]
+  pub network: Network,
+  /// hex encoded hash the spec is expected to produce, checked on load
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub hash: Option<String>,
+}
+
+impl GenesisSpec {
+  pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+    let content = fs::read_to_string(path)?;
+    let spec: Self = serde_json::from_str(&content)?;
+
+    if let Some(expected_hash) = spec.hash.as_ref() {
+      let actual_hash = hex::encode(spec.block().hash());
+
+      if *expected_hash != actual_hash {
+        return Err(io::Error::new(
+          ErrorKind::InvalidData,
+          format!("genesis hash mismatch: expected {}, got {}", expected_hash, actual_hash),
+        ));
+      }
+    }
+
+    Ok(spec)
+  }
+
+  pub fn block(&self) -> Block {
+    let mut block = Block::new(0, vec![0_u8; 32]);
+    block.header.timestamp = self.timestamp;
+    block.header.difficulty = self.difficulty;
+
+    if self.ledger == LedgerMode::Utxo {
+      if !self.allocations.is_empty() {
+        let outputs = self.allocations
+          .iter()
+          .map(|allocation| TxOutput { address: allocation.address.clone(), amount: allocation.amount })
+          .collect();
+
+        let coinbase = UtxoTransaction::coinbase(outputs, 0, &self.chain_id);
+
+        block.transactions.push(Serializable::serialize(&coinbase));
+      }
+
+      block.update_merkle_root();
+
+      return block;
+    }
+
+    for allocation in self.allocations.iter() {
+      let raw_trx = RawTransaction::new(
+        Blockchain::MINING_SENDER.as_bytes().to_vec(),
+        allocation.address.as_bytes().to_vec(),
+        allocation.amount,
+        0,
+        self.chain_id.as_bytes().to_vec(),
+      );
+
+      block.transactions.push(raw_trx.serialize());
+    }
+
+    block.update_merkle_root();
+
+    block
+  }
+
+  pub fn hash(&self) -> Vec<u8> {
+    self.block().hash()
+  }
+
+  pub fn schedule(&self) -> Schedule {
+    Schedule { target_block_time_ms: self.target_block_time_ms, retarget_interval: self.retarget_interval }
+  }
+
+  fn default_target_block_time_ms() -> u64 {
+    10_000
+  }
+
+  fn default_retarget_interval() -> u64 {
+    10
+  }
+}
+
+/// Mainnet parameters without a pinned hash, meant as a base for custom
+/// specs. Nodes start from `Network::genesis`, which pins it.
+impl Default for GenesisSpec {
+  fn default() -> Self {
+    Self {
+      // 2025-01-01T00:00:00Z
+      timestamp: 1_735_689_600_000_000_000,
+      allocations: vec![],
+      difficulty: 16,
+      target_block_time_ms: Self::default_target_block_time_ms(),
+      retarget_interval: Self::default_retarget_interval(),
+      mining_reward: Amount::from_coins(1).unwrap(),
+      chain_id: "thursque".to_string(),
+      ledger: LedgerMode::Account,
+      network: Network::Mainnet,
+      hash: None,
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{GenesisAllocation, GenesisSpec};
+  use crate::core::{amount::Amount, blockchain::Blockchain};
+
+  #[test]
+  fn test_genesis_is_deterministic() {
+    let spec = GenesisSpec {
+      allocations: vec![GenesisAllocation { address: "alice".into(), amount: Amount::from_coins(50).unwrap() }],
+      ..GenesisSpec::default()
+    };
+
+    let first = Blockchain::with_genesis("miner-a".into(), spec.clone());
+    let second = Blockchain::with_genesis("miner-b".into(), spec.clone());
+
+    assert_eq!(first.genesis_hash(), second.genesis_hash());
+    assert!(second.chain_is_valid(&first.chain));
+    assert_eq!(first.calculate_reward("alice".into()), Amount::from_coins(50).unwrap());
+    assert!(!Blockchain::new("miner-c".into()).shares_genesis(&first.chain));
+  }
+}
@@ -0,0 +1,132 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use super::{difficulty::MIN_DIFFICULTY, error::UnknownNetworkError, genesis::GenesisSpec};
+
+/// Network a node, wallet or address belongs to. Addresses carry it in their
+/// version byte so coins can never be sent across networks by mistake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+  #[default]
+  Mainnet,
+  Testnet,
+  Regtest,
+}
+
+impl Network {
+  pub const ALL: [Network; 3] = [Network::Mainnet, Network::Testnet, Network::Regtest];
+
+  /// Leading byte of every Base58Check address on this network.
+  pub fn address_version(self) -> u8 {
+    match self {
+      Network::Mainnet => 0x00,
+      Network::Testnet => 0x6f,
+      Network::Regtest => 0x3c,
+    }
+  }
+
+  /// Human readable prefix of Bech32 addresses on this network.
+  pub fn bech32_hrp(self) -> &'static str {
+    match self {
+      Network::Mainnet => "tq",
+      Network::Testnet => "tqt",
+      Network::Regtest => "tqrt",
+    }
+  }
+
+  /// BIP44 coin type HD wallets derive keys under, every test network
+  /// shares the conventional `1`.
+  pub fn coin_type(self) -> u32 {
+    match self {
+      Network::Mainnet => 7471,
+      Network::Testnet | Network::Regtest => 1,
+    }
+  }
+
+  pub fn from_address_version(version: u8) -> Option<Network> {
+    Self::ALL.into_iter().find(|network| network.address_version() == version)
+  }
+
+  /// First port nodes of this network listen on and look for neighbors from.
+  pub fn default_port(self) -> u16 {
+    match self {
+      Network::Mainnet => 8000,
+      Network::Testnet => 18000,
+      Network::Regtest => 28000,
+    }
+  }
+
+  /// Hex encoded hash of the built in genesis block, pinned so a change to
+  /// the block encoding cannot silently fork the network.
+  pub fn genesis_hash(self) -> &'static str {
+    match self {
+      Network::Mainnet => "c7b4f97517f8abc644f9991928d4f44bc32924d2f5ec5748e13d2d08f951f661",
+      Network::Testnet => "729af00aa7bd796ca0b1aad046bdee83a719dd2123f5cc5513b7b58f9433a01c",
+      Network::Regtest => "0d87c9051dc04ac73befb0ed0ef08b533ad65498fe40a07d44b60e9e666dc12b",
+    }
+  }
+
+  /// Genesis spec used when a node is not given one, it also sets the
+  /// starting difficulty of the network.
+  pub fn genesis(self) -> GenesisSpec {
+    let spec = match self {
+      Network::Mainnet => GenesisSpec::default(),
+      Network::Testnet => GenesisSpec {
+        chain_id: "thursque-testnet".to_string(),
+        difficulty: 12,
+        network: self,
+        ..GenesisSpec::default()
+      },
+      // blocks are mined on demand in tests, so keep them instant
+      Network::Regtest => GenesisSpec {
+        chain_id: "thursque-regtest".to_string(),
+        difficulty: MIN_DIFFICULTY,
+        retarget_interval: u64::MAX,
+        network: self,
+        ..GenesisSpec::default()
+      },
+    };
+
+    GenesisSpec { hash: Some(self.genesis_hash().to_string()), ..spec }
+  }
+}
+
+impl Display for Network {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let name = match self {
+      Network::Mainnet => "mainnet",
+      Network::Testnet => "testnet",
+      Network::Regtest => "regtest",
+    };
+
+    write!(f, "{}", name)
+  }
+}
+
+impl FromStr for Network {
+  type Err = UnknownNetworkError;
+
+  fn from_str(name: &str) -> Result<Self, Self::Err> {
+    Self::ALL
+      .into_iter()
+      .find(|network| network.to_string() == name.to_lowercase())
+      .ok_or_else(|| UnknownNetworkError(name.to_string()))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::Network;
+
+  #[test]
+  fn test_builtin_genesis_hashes_are_pinned() {
+    for network in Network::ALL {
+      let spec = network.genesis();
+
+      assert_eq!(hex::encode(spec.hash()), network.genesis_hash(), "{} genesis changed", network);
+      assert_eq!(spec.hash.as_deref(), Some(network.genesis_hash()));
+    }
+  }
+}
\ No newline at end of file
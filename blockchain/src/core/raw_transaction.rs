@@ -1,65 +1,91 @@
 use std::fmt::Display;
 
-use crate::utils::serializable::Serializable;
+use crate::utils::serializable::{write_field, FieldReader, Serializable};
 
+use super::{amount::Amount, error::DecodeError, transaction::Transaction};
+
+/// Signed transaction record as stored in `Block::transactions`.
+///
+/// The public key and signature are kept as raw bytes next to the transfer so a
+/// mined block can be verified again later on.
 #[derive(Debug)]
 pub struct RawTransaction {
   pub sender_address: Vec<u8>,
   pub recipient_address: Vec<u8>,
-  pub value: f64,
+  pub value: Amount,
+  pub nonce: u64,
+  pub chain_id: Vec<u8>,
+  pub public_key: Vec<u8>,
+  pub signature: Vec<u8>,
 }
 
 impl RawTransaction {
-  pub fn new(sender_address: Vec<u8>, recipient_address: Vec<u8>, value: f64) -> Self {
-    Self { sender_address, recipient_address, value }
+  pub fn new(sender_address: Vec<u8>, recipient_address: Vec<u8>, value: Amount, nonce: u64, chain_id: Vec<u8>) -> Self {
+    Self { sender_address, recipient_address, value, nonce, chain_id, public_key: vec![], signature: vec![] }
+  }
+
+  /// Fails when the hex encoded public key or signature cannot be decoded.
+  pub fn from_transaction(transaction: &Transaction) -> Result<Self, DecodeError> {
+    Ok(Self {
+      sender_address: transaction.sender.as_bytes().to_vec(),
+      recipient_address: transaction.receiver.as_bytes().to_vec(),
+      value: transaction.amount,
+      nonce: transaction.nonce,
+      chain_id: transaction.chain_id.as_bytes().to_vec(),
+      public_key: hex::decode(&transaction.public_key).map_err(|_| DecodeError::InvalidField("public_key"))?,
+      signature: hex::decode(&transaction.signature).map_err(|_| DecodeError::InvalidField("signature"))?,
+    })
+  }
+
+  /// Rebuild the signed transaction, failing when a text field is not valid utf-8.
+  pub fn to_transaction(&self) -> Result<Transaction, DecodeError> {
+    let text = |bytes: &[u8], name| String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidField(name));
+
+    Ok(Transaction {
+      sender: text(&self.sender_address, "sender_address")?,
+      receiver: text(&self.recipient_address, "recipient_address")?,
+      amount: self.value,
+      nonce: self.nonce,
+      chain_id: text(&self.chain_id, "chain_id")?,
+      public_key: hex::encode(&self.public_key),
+      signature: hex::encode(&self.signature),
+    })
   }
 }
 
 impl Serializable <RawTransaction> for RawTransaction {
-  fn serialize(&self) -> Vec<u8> {
-    let mut serialized = vec![];
-
-    let sender_address_len = self.sender_address.len();
-    serialized.extend(sender_address_len.to_be_bytes().to_vec());
-    serialized.extend(&self.sender_address);
+  const VERSION: u8 = 2;
 
-    let recipient_address_len = self.recipient_address.len();
-    serialized.extend(recipient_address_len.to_be_bytes().to_vec());
-    serialized.extend(&self.recipient_address);
+  fn serialize(&self) -> Vec<u8> {
+    let mut serialized = vec![Self::VERSION];
 
-    let value_len = self.value.to_be_bytes().len();
-    serialized.extend(value_len.to_be_bytes().to_vec());
-    serialized.extend(self.value.to_be_bytes().to_vec());
+    write_field(&mut serialized, &self.sender_address);
+    write_field(&mut serialized, &self.recipient_address);
+    write_field(&mut serialized, &self.value.base_units().to_be_bytes());
+    write_field(&mut serialized, &self.nonce.to_be_bytes());
+    write_field(&mut serialized, &self.chain_id);
+    write_field(&mut serialized, &self.public_key);
+    write_field(&mut serialized, &self.signature);
 
     serialized
   }
 
-  fn deserialize(bytes: Vec<u8>) -> RawTransaction {
-    let mut pos = 0;
-
-    // sender address
-    let sender_address_len = usize::from_be_bytes(bytes[pos..pos + 8].try_into().unwrap());
-    let mut sender_address = Vec::<u8>::new();
-
-    pos += 8;
-    sender_address.extend_from_slice(&bytes[pos..pos + sender_address_len]);
-    pos += sender_address_len;
+  fn deserialize(bytes: &[u8]) -> Result<RawTransaction, DecodeError> {
+    let mut reader = FieldReader::new(bytes);
 
-    // recipient address
-    let recipient_address_len = usize::from_be_bytes(bytes[pos..pos + 8].try_into().unwrap());
-    let mut recipient_address = Vec::<u8>::new();
+    reader.read_version(Self::VERSION)?;
 
-    pos += 8;
-    recipient_address.extend_from_slice(&bytes[pos..pos + recipient_address_len]);
-    pos += recipient_address_len;
+    let sender_address = reader.read_field()?.to_vec();
+    let recipient_address = reader.read_field()?.to_vec();
+    let value = Amount::from_base_units(reader.read_u64("value")?).ok_or(DecodeError::InvalidField("value"))?;
+    let nonce = reader.read_u64("nonce")?;
+    let chain_id = reader.read_field()?.to_vec();
+    let public_key = reader.read_field()?.to_vec();
+    let signature = reader.read_field()?.to_vec();
 
-    // value
-    let value_len = usize::from_be_bytes(bytes[pos..pos + 8].try_into().unwrap());
+    reader.finish()?;
 
-    pos += 8;
-    let value = f64::from_be_bytes(bytes[pos..pos + value_len].try_into().unwrap());
-
-    RawTransaction { sender_address, recipient_address, value }
+    Ok(RawTransaction { sender_address, recipient_address, value, nonce, chain_id, public_key, signature })
   }
 }
 
@@ -67,12 +93,38 @@ impl Display for RawTransaction {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     write!(
       f,
-      "\n{}\nSender Address: {:?}\nReceiver Address: {:?}\nTransaction: {:?}\n{}",
+      "\n{}\nSender Address: {:?}\nReceiver Address: {:?}\nTransaction: {}\nPublic Key: {}\nSignature: {}\n{}",
       "-".repeat(40),
-      String::from_utf8(self.sender_address.clone()).unwrap(),
-      String::from_utf8(self.recipient_address.clone()).unwrap(),
+      String::from_utf8_lossy(&self.sender_address),
+      String::from_utf8_lossy(&self.recipient_address),
       self.value,
+      hex::encode(&self.public_key),
+      hex::encode(&self.signature),
       "-".repeat(40),
     )
   }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+  use super::RawTransaction;
+  use crate::{core::{amount::Amount, error::DecodeError, wallet::Wallet}, utils::serializable::Serializable};
+
+  #[test]
+  fn test_signed_record_roundtrip() {
+    let wallet = Wallet::default();
+    let transaction = wallet.sign_transaction(Wallet::default().address(), "2.5".parse::<Amount>().unwrap(), 0, "test");
+
+    let mut serialized = RawTransaction::from_transaction(&transaction).unwrap().serialize();
+    let restored = RawTransaction::deserialize(&serialized).unwrap().to_transaction().unwrap();
+
+    assert_eq!(restored.public_key, transaction.public_key);
+    assert_eq!(restored.signature, transaction.signature);
+    assert_eq!(Wallet::verify_transaction(&restored), Ok(()));
+
+    assert_eq!(RawTransaction::deserialize(&serialized[..serialized.len() - 1]).unwrap_err(), DecodeError::UnexpectedEnd);
+
+    serialized[0] = RawTransaction::VERSION + 1;
+    assert_eq!(RawTransaction::deserialize(&serialized).unwrap_err(), DecodeError::UnsupportedVersion(RawTransaction::VERSION + 1));
+  }
+}
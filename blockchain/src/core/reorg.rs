@@ -0,0 +1,30 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+/// Outcome of switching the local chain over to another branch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReorgEvent {
+  /// height of the last block both branches share
+  pub common_ancestor: usize,
+  /// number of local blocks that were rolled back
+  pub depth: usize,
+  pub old_tip: Vec<u8>,
+  pub new_tip: Vec<u8>,
+  /// transactions of rolled back blocks that went back into the pool
+  pub reinjected: usize,
+}
+
+impl Display for ReorgEvent {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "reorg of depth {} at height {}: tip {} -> {}, {} transactions returned to the pool",
+      self.depth,
+      self.common_ancestor,
+      hex::encode(&self.old_tip),
+      hex::encode(&self.new_tip),
+      self.reinjected,
+    )
+  }
+}
@@ -0,0 +1,335 @@
+use std::{
+  fs::{self, File, OpenOptions},
+  io::{self, ErrorKind, Read, Seek, SeekFrom, Write},
+  path::{Path, PathBuf},
+};
+
+use crate::utils::{hash::hash, serializable::Serializable};
+
+use super::block::Block;
+
+const DATA_FILE: &str = "blocks.dat";
+const INDEX_FILE: &str = "blocks.idx";
+const RECORD_HEADER_LEN: u64 = 8;
+
+/// Embedded append-only block store.
+///
+/// Every block is written to `blocks.dat` as a record made of a 4 byte length,
+/// a 4 byte checksum and the block in its `Serializable` encoding. `blocks.idx`
+/// holds the offset of each record and acts as the commit log: a block only
+/// counts as stored once its offset has been synced to the index, so anything
+/// found past the last indexed record after a crash is discarded on the next
+/// `open`.
+#[derive(Debug, Clone)]
+pub struct BlockStore {
+  dir: PathBuf,
+  offsets: Vec<u64>,
+  data_len: u64,
+}
+
+impl BlockStore {
+  pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+    let dir = dir.as_ref().to_path_buf();
+    fs::create_dir_all(&dir)?;
+
+    let mut store = Self { dir, offsets: vec![], data_len: 0 };
+    store.recover()?;
+
+    Ok(store)
+  }
+
+  pub fn len(&self) -> usize {
+    self.offsets.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.offsets.is_empty()
+  }
+
+  pub fn append(&mut self, block: &Block) -> io::Result<()> {
+    let record = Self::encode_record(&block.serialize());
+
+    let offset = self.data_len;
+
+    // write at the committed length, dropping whatever a failed append left behind
+    let mut data = OpenOptions::new().create(true).truncate(false).write(true).open(self.data_path())?;
+    data.set_len(offset)?;
+    data.seek(SeekFrom::Start(offset))?;
+    data.write_all(&record)?;
+    data.sync_all()?;
+
+    let index_len = self.offsets.len() as u64 * 8;
+
+    let mut index = OpenOptions::new().create(true).truncate(false).write(true).open(self.index_path())?;
+    index.set_len(index_len)?;
+    index.seek(SeekFrom::Start(index_len))?;
+    index.write_all(&offset.to_be_bytes())?;
+    index.sync_all()?;
+
+    self.offsets.push(offset);
+    self.data_len += record.len() as u64;
+
+    Ok(())
+  }
+
+  pub fn read_block(&self, height: usize) -> io::Result<Option<Block>> {
+    let offset = match self.offsets.get(height) {
+      Some(offset) => *offset,
+      None => return Ok(None),
+    };
+
+    let mut data = File::open(self.data_path())?;
+    data.seek(SeekFrom::Start(offset))?;
+
+    let payload = Self::read_record(&mut data)?
+      .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, format!("missing block record at height {}", height)))?;
+
+    let block = Block::deserialize(&payload).map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+
+    Ok(Some(block))
+  }
+
+  pub fn load_blocks(&self) -> io::Result<Vec<Block>> {
+    let mut blocks = Vec::with_capacity(self.offsets.len());
+
+    for height in 0..self.offsets.len() {
+      if let Some(block) = self.read_block(height)? {
+        blocks.push(block);
+      }
+    }
+
+    Ok(blocks)
+  }
+
+  /// Drop every block from `height` onwards.
+  ///
+  /// The index is shortened first so that a crash half-way through leaves the
+  /// removed records uncommitted rather than resurrecting them.
+  pub fn truncate(&mut self, height: usize) -> io::Result<()> {
+    if height >= self.offsets.len() {
+      return Ok(());
+    }
+
+    let data_len = self.offsets[height];
+
+    let index = OpenOptions::new().write(true).open(self.index_path())?;
+    index.set_len(height as u64 * 8)?;
+    index.sync_all()?;
+
+    let data = OpenOptions::new().write(true).open(self.data_path())?;
+    data.set_len(data_len)?;
+    data.sync_all()?;
+
+    self.offsets.truncate(height);
+    self.data_len = data_len;
+
+    Ok(())
+  }
+
+  fn recover(&mut self) -> io::Result<()> {
+    let mut index_bytes = vec![];
+
+    if let Ok(mut index) = File::open(self.index_path()) {
+      index.read_to_end(&mut index_bytes)?;
+    }
+
+    let mut data = OpenOptions::new().create(true).truncate(false).read(true).write(true).open(self.data_path())?;
+    let data_file_len = data.metadata()?.len();
+
+    // keep only the index entries pointing at complete, intact records
+    let mut offsets = vec![];
+    let mut data_len = 0;
+
+    for entry in index_bytes.chunks_exact(8) {
+      let offset = u64::from_be_bytes(entry.try_into().unwrap());
+
+      if offset != data_len || offset + RECORD_HEADER_LEN > data_file_len {
+        break;
+      }
+
+      data.seek(SeekFrom::Start(offset))?;
+
+      match Self::read_record(&mut data) {
+        Ok(Some(payload)) => {
+          offsets.push(offset);
+          data_len = offset + RECORD_HEADER_LEN + payload.len() as u64;
+        }
+        _ => break,
+      }
+    }
+
+    let index_is_dirty = offsets.len() * 8 != index_bytes.len();
+
+    if index_is_dirty {
+      let index_bytes: Vec<u8> = offsets.iter().flat_map(|offset| offset.to_be_bytes()).collect();
+      self.write_atomically(INDEX_FILE, &index_bytes)?;
+    }
+
+    if data_file_len != data_len {
+      data.set_len(data_len)?;
+      data.sync_all()?;
+    }
+
+    self.offsets = offsets;
+    self.data_len = data_len;
+
+    Ok(())
+  }
+
+  fn encode_record(payload: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(payload.len() + RECORD_HEADER_LEN as usize);
+
+    record.extend((payload.len() as u32).to_be_bytes());
+    record.extend(&hash(payload.to_vec())[0..4]);
+    record.extend(payload);
+
+    record
+  }
+
+  fn read_record(data: &mut File) -> io::Result<Option<Vec<u8>>> {
+    let mut header = [0u8; RECORD_HEADER_LEN as usize];
+
+    match data.read_exact(&mut header) {
+      Ok(()) => {}
+      Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+      Err(err) => return Err(err),
+    }
+
+    let payload_len = u32::from_be_bytes(header[0..4].try_into().unwrap()) as u64;
+
+    // a corrupt header must not make us allocate more than the file holds
+    if payload_len > data.metadata()?.len().saturating_sub(data.stream_position()?) {
+      return Ok(None);
+    }
+
+    let mut payload = vec![0u8; payload_len as usize];
+
+    match data.read_exact(&mut payload) {
+      Ok(()) => {}
+      Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+      Err(err) => return Err(err),
+    }
+
+    if hash(payload.clone())[0..4] != header[4..8] {
+      return Ok(None);
+    }
+
+    Ok(Some(payload))
+  }
+
+  fn write_atomically(&self, file_name: &str, bytes: &[u8]) -> io::Result<()> {
+    let tmp_path = self.dir.join(format!("{}.tmp", file_name));
+
+    let mut tmp = File::create(&tmp_path)?;
+    tmp.write_all(bytes)?;
+    tmp.sync_all()?;
+
+    fs::rename(tmp_path, self.dir.join(file_name))?;
+
+    Ok(())
+  }
+
+  fn data_path(&self) -> PathBuf {
+    self.dir.join(DATA_FILE)
+  }
+
+  fn index_path(&self) -> PathBuf {
+    self.dir.join(INDEX_FILE)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::{fs::{self, OpenOptions}, io::Write, path::PathBuf};
+
+  use super::BlockStore;
+  use crate::core::block::Block;
+
+  fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("thursque-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    dir
+  }
+
+  #[test]
+  fn test_blocks_survive_reopen() {
+    let dir = temp_dir("store-reopen");
+    let first = Block::new(0, vec![0; 32]);
+    let second = Block::new(1, first.hash());
+
+    {
+      let mut store = BlockStore::open(&dir).unwrap();
+      store.append(&first).unwrap();
+      store.append(&second).unwrap();
+    }
+
+    let store = BlockStore::open(&dir).unwrap();
+
+    assert_eq!(store.load_blocks().unwrap(), vec![first, second.clone()]);
+    assert_eq!(store.read_block(1).unwrap(), Some(second));
+
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn test_torn_write_is_discarded() {
+    let dir = temp_dir("store-torn");
+    let block = Block::new(0, vec![0; 32]);
+
+    {
+      let mut store = BlockStore::open(&dir).unwrap();
+      store.append(&block).unwrap();
+    }
+
+    // simulate a crash half-way through appending the next record
+    let mut data = OpenOptions::new().append(true).open(dir.join("blocks.dat")).unwrap();
+    data.write_all(&[0, 0, 1, 0, 42]).unwrap();
+
+    let mut store = BlockStore::open(&dir).unwrap();
+    assert_eq!(store.len(), 1);
+
+    store.append(&block).unwrap();
+    assert_eq!(BlockStore::open(&dir).unwrap().load_blocks().unwrap().len(), 2);
+
+    fs::remove_dir_all(dir).unwrap();
+  }
+  #[test]
+  fn test_uncommitted_record_is_overwritten() {
+    let dir = temp_dir("store-uncommitted");
+    let first = Block::new(0, vec![0; 32]);
+    let second = Block::new(1, first.hash());
+
+    let mut store = BlockStore::open(&dir).unwrap();
+    store.append(&first).unwrap();
+
+    // a record whose index entry was never written, as after a failed append
+    let mut data = OpenOptions::new().append(true).open(dir.join("blocks.dat")).unwrap();
+    data.write_all(&BlockStore::encode_record(b"stale")).unwrap();
+
+    store.append(&second).unwrap();
+    assert_eq!(store.read_block(1).unwrap(), Some(second.clone()));
+    assert_eq!(BlockStore::open(&dir).unwrap().load_blocks().unwrap(), vec![first, second]);
+
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn test_oversized_record_header_is_discarded() {
+    let dir = temp_dir("store-oversized");
+
+    {
+      let mut store = BlockStore::open(&dir).unwrap();
+      store.append(&Block::new(0, vec![0; 32])).unwrap();
+    }
+
+    let mut data = OpenOptions::new().write(true).open(dir.join("blocks.dat")).unwrap();
+    data.write_all(&u32::MAX.to_be_bytes()).unwrap();
+
+    let store = BlockStore::open(&dir).unwrap();
+    assert_eq!(store.len(), 0);
+    assert_eq!(fs::metadata(dir.join("blocks.dat")).unwrap().len(), 0);
+
+    fs::remove_dir_all(dir).unwrap();
+  }
+}
@@ -1,10 +1,105 @@
 use serde::{Deserialize, Serialize};
 
+use crate::utils::{hash::hash, serializable::write_field};
+
+use super::amount::Amount;
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Transaction {
   pub sender: String,
   pub receiver: String,
-  pub amount: f64,
+  pub amount: Amount,
+  /// position of this transaction among all transactions sent by `sender`
+  pub nonce: u64,
+  pub chain_id: String,
   pub public_key: String,
   pub signature: String,
-}
\ No newline at end of file
+}
+
+impl Transaction {
+  /// Prefix of every signing payload, so a transaction signature can never be
+  /// replayed as a signature over any other kind of message.
+  pub const SIGNING_DOMAIN: &'static [u8] = b"blockchain-transaction-v1";
+
+  /// Canonical encoding of everything the signature covers:
+  ///
+  /// ```text
+  /// SIGNING_DOMAIN
+  /// u64 len | sender      (utf-8)
+  /// u64 len | receiver    (utf-8)
+  /// u64 len | amount      (u64 base units)
+  /// u64 len | nonce       (u64)
+  /// u64 len | chain_id    (utf-8)
+  /// ```
+  ///
+  /// Every integer is big endian. The public key is not part of the payload,
+  /// it is committed to by being the key the signature verifies against.
+  pub fn signing_payload(&self) -> Vec<u8> {
+    let mut payload = Self::SIGNING_DOMAIN.to_vec();
+
+    write_field(&mut payload, self.sender.as_bytes());
+    write_field(&mut payload, self.receiver.as_bytes());
+    write_field(&mut payload, &self.amount.base_units().to_be_bytes());
+    write_field(&mut payload, &self.nonce.to_be_bytes());
+    write_field(&mut payload, self.chain_id.as_bytes());
+
+    payload
+  }
+
+  /// SHA-256 of `signing_payload`, the digest that is signed with ECDSA P-256.
+  pub fn signing_hash(&self) -> Vec<u8> {
+    hash(self.signing_payload())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::core::{amount::Amount, error::TransactionError, network::Network, wallet::Wallet};
+
+  // Test vectors for clients producing signatures outside of this crate.
+  // The private key is 32 bytes of 0x01, signatures use RFC 6979 nonces.
+  const PRIVATE_KEY: &str = "0101010101010101010101010101010101010101010101010101010101010101";
+  const PUBLIC_KEY: &str = "6ff03b949241ce1dadd43519e6960e0a85b41a69a05c328103aa2bce1594ca16\
+    3c4f753a55bf01dc53f6c0b0c7eee78b40c6ff7d25a96e2282b989cef71c144a";
+  const SENDER: &str = "1Ji9MaGamXWmq7MMAvuXdgaACgTRHzPZ6W";
+  const RECEIVER: &str = "1BoatSLRHtKNngkdXEeobR76b53LETtpyT";
+
+  #[test]
+  fn test_signing_payload_vectors() {
+    let wallet = Wallet::new_from(PUBLIC_KEY, PRIVATE_KEY, SENDER).unwrap();
+    let transaction = wallet.sign_transaction(RECEIVER.to_string(), "1.5".parse::<Amount>().unwrap(), 3, "mainnet");
+
+    assert_eq!(
+      hex::encode(transaction.signing_payload()),
+      concat!(
+        "626c6f636b636861696e2d7472616e73616374696f6e2d7631",
+        "0000000000000022", "314a69394d6147616d58576d71374d4d417675586467614143675452487a505a3657",
+        "0000000000000022", "31426f6174534c5248744b4e6e676b645845656f625237366235334c455474707954",
+        "0000000000000008", "0000000008f0d180",
+        "0000000000000008", "0000000000000003",
+        "0000000000000007", "6d61696e6e6574",
+      ),
+    );
+    assert_eq!(
+      hex::encode(transaction.signing_hash()),
+      "2ca408f8bdab3c79bd9989382502872b12df924430a92e06499cca8aeb92685b",
+    );
+    assert_eq!(
+      transaction.signature,
+      concat!(
+        "d20d2094ac9067ce5950a9d4ccd5a15d224b0cf4037ac034bdcce5711a274680",
+        "475e70ce356fa2b043388954fe1dcce5dcffd04457b1d8982d6f5525e33e9803",
+      ),
+    );
+    assert_eq!(Wallet::address_of(PUBLIC_KEY, Network::Mainnet).unwrap(), SENDER);
+    assert_eq!(Wallet::verify_transaction(&transaction), Ok(()));
+
+    let mut tampered = transaction.clone();
+    tampered.nonce += 1;
+    assert_eq!(Wallet::verify_transaction(&tampered), Err(TransactionError::InvalidSignature));
+
+    let mut impersonated = transaction.clone();
+    impersonated.sender = RECEIVER.to_string();
+    assert_eq!(Wallet::verify_transaction(&impersonated), Err(TransactionError::SenderMismatch));
+  }
+}
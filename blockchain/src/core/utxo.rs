@@ -0,0 +1,304 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{
+  address::{self, LedgerKey},
+  hash::hash,
+  serializable::{write_field, FieldReader, Serializable},
+};
+
+use super::{amount::Amount, error::DecodeError};
+
+/// Which ledger model a chain keeps its balances in, fixed by the genesis spec.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LedgerMode {
+  /// balances are running totals per address, see `RawTransaction`
+  #[default]
+  Account,
+  /// balances are the unspent outputs of `UtxoTransaction`s
+  Utxo,
+}
+
+/// Reference to one output of an earlier transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct OutPoint {
+  pub txid: String,
+  pub index: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TxOutput {
+  pub address: String,
+  pub amount: Amount,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TxInput {
+  pub previous_output: OutPoint,
+  pub public_key: String,
+  pub signature: String,
+}
+
+/// Transaction of the UTXO ledger: it spends whole outputs of earlier
+/// transactions and creates new ones, sending any change back to the sender.
+///
+/// A transaction without inputs is a coinbase minting new coins, its `height`
+/// is the height of the block it was mined in so every coinbase gets a unique
+/// id. Regular transfers leave `height` at 0.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UtxoTransaction {
+  pub inputs: Vec<TxInput>,
+  pub outputs: Vec<TxOutput>,
+  pub chain_id: String,
+  pub height: u64,
+}
+
+impl UtxoTransaction {
+  pub fn coinbase(outputs: Vec<TxOutput>, height: u64, chain_id: &str) -> Self {
+    Self { inputs: vec![], outputs, chain_id: chain_id.to_string(), height }
+  }
+
+  pub fn is_coinbase(&self) -> bool {
+    self.inputs.is_empty()
+  }
+
+  /// Prefix of every input's signing payload, distinct from the account
+  /// ledger's `Transaction::SIGNING_DOMAIN`.
+  pub const SIGNING_DOMAIN: &'static [u8] = b"blockchain-utxo-transaction-v1";
+
+  /// Hash of the transaction with every signature left out, the id outputs
+  /// are referenced by.
+  pub fn txid_bytes(&self) -> Vec<u8> {
+    hash(Serializable::serialize(&self.unsigned()))
+  }
+
+  /// What every input signs: `SIGNING_DOMAIN` followed by the encoding of the
+  /// transaction with every signature left out.
+  pub fn signing_payload(&self) -> Vec<u8> {
+    let mut payload = Self::SIGNING_DOMAIN.to_vec();
+    payload.extend(Serializable::serialize(&self.unsigned()));
+
+    payload
+  }
+
+  /// SHA-256 of `signing_payload`, the digest that is signed with ECDSA P-256.
+  pub fn signing_hash(&self) -> Vec<u8> {
+    hash(self.signing_payload())
+  }
+
+  fn unsigned(&self) -> Self {
+    let mut unsigned = self.clone();
+
+    for input in unsigned.inputs.iter_mut() {
+      input.signature = String::new();
+    }
+
+    unsigned
+  }
+
+  pub fn txid(&self) -> String {
+    hex::encode(self.txid_bytes())
+  }
+
+  pub fn output_total(&self) -> Option<Amount> {
+    self.outputs.iter().try_fold(Amount::ZERO, |total, output| total.checked_add(output.amount))
+  }
+}
+
+impl Serializable <UtxoTransaction> for UtxoTransaction {
+  /// kept apart from the account ledger's `RawTransaction` version
+  const VERSION: u8 = 0x81;
+
+  fn serialize(&self) -> Vec<u8> {
+    let mut serialized = vec![Self::VERSION];
+
+    write_field(&mut serialized, self.chain_id.as_bytes());
+    write_field(&mut serialized, &self.height.to_be_bytes());
+
+    write_field(&mut serialized, &(self.inputs.len() as u64).to_be_bytes());
+
+    for input in self.inputs.iter() {
+      write_field(&mut serialized, input.previous_output.txid.as_bytes());
+      write_field(&mut serialized, &(input.previous_output.index as u64).to_be_bytes());
+      write_field(&mut serialized, input.public_key.as_bytes());
+      write_field(&mut serialized, input.signature.as_bytes());
+    }
+
+    write_field(&mut serialized, &(self.outputs.len() as u64).to_be_bytes());
+
+    for output in self.outputs.iter() {
+      write_field(&mut serialized, output.address.as_bytes());
+      write_field(&mut serialized, &output.amount.base_units().to_be_bytes());
+    }
+
+    serialized
+  }
+
+  fn deserialize(bytes: &[u8]) -> Result<UtxoTransaction, DecodeError> {
+    let mut reader = FieldReader::new(bytes);
+
+    reader.read_version(Self::VERSION)?;
+
+    let chain_id = reader.read_string("chain_id")?;
+    let height = reader.read_u64("height")?;
+
+    let input_count = reader.read_u64("input_count")?;
+    let mut inputs = vec![];
+
+    for _ in 0..input_count {
+      let txid = reader.read_string("txid")?;
+      let index = u32::try_from(reader.read_u64("index")?).map_err(|_| DecodeError::InvalidField("index"))?;
+
+      inputs.push(TxInput {
+        previous_output: OutPoint { txid, index },
+        public_key: reader.read_string("public_key")?,
+        signature: reader.read_string("signature")?,
+      });
+    }
+
+    let output_count = reader.read_u64("output_count")?;
+    let mut outputs = vec![];
+
+    for _ in 0..output_count {
+      let address = reader.read_string("address")?;
+      let amount = Amount::from_base_units(reader.read_u64("amount")?).ok_or(DecodeError::InvalidField("amount"))?;
+
+      outputs.push(TxOutput { address, amount });
+    }
+
+    reader.finish()?;
+
+    Ok(UtxoTransaction { inputs, outputs, chain_id, height })
+  }
+}
+
+/// Every output that has not been spent yet, keyed by where it was created.
+#[derive(Debug, Clone, Default)]
+pub struct UtxoSet {
+  outputs: HashMap<OutPoint, TxOutput>,
+  /// where the outputs of each owner are, so lookups do not decode every address
+  by_owner: HashMap<LedgerKey, HashSet<OutPoint>>,
+}
+
+impl UtxoSet {
+  pub fn get(&self, out_point: &OutPoint) -> Option<&TxOutput> {
+    self.outputs.get(out_point)
+  }
+
+  /// Spend the inputs of `transaction` and add its outputs. Inputs must have
+  /// been checked against the set beforehand.
+  pub fn apply(&mut self, transaction: &UtxoTransaction) {
+    for input in transaction.inputs.iter() {
+      let Some(output) = self.outputs.remove(&input.previous_output) else {
+        continue;
+      };
+
+      let owner = address::ledger_key(&output.address);
+
+      if let Some(out_points) = self.by_owner.get_mut(&owner) {
+        out_points.remove(&input.previous_output);
+
+        if out_points.is_empty() {
+          self.by_owner.remove(&owner);
+        }
+      }
+    }
+
+    let txid = transaction.txid();
+
+    for (index, output) in transaction.outputs.iter().enumerate() {
+      let out_point = OutPoint { txid: txid.clone(), index: index as u32 };
+
+      self.by_owner.entry(address::ledger_key(&output.address)).or_default().insert(out_point.clone());
+      self.outputs.insert(out_point, output.clone());
+    }
+  }
+
+  /// Outputs paid to `address` in any of its formats.
+  pub fn unspent_for(&self, address: &str) -> Vec<(OutPoint, TxOutput)> {
+    let Some(out_points) = self.by_owner.get(&address::ledger_key(address)) else {
+      return vec![];
+    };
+
+    let mut unspent: Vec<(OutPoint, TxOutput)> = out_points
+      .iter()
+      .map(|out_point| (out_point.clone(), self.outputs[out_point].clone()))
+      .collect();
+
+    unspent.sort_by(|a, b| (&a.0.txid, a.0.index).cmp(&(&b.0.txid, b.0.index)));
+
+    unspent
+  }
+
+  pub fn balance(&self, address: &str) -> Amount {
+    let Some(out_points) = self.by_owner.get(&address::ledger_key(address)) else {
+      return Amount::ZERO;
+    };
+
+    out_points
+      .iter()
+      .map(|out_point| &self.outputs[out_point])
+      .fold(Amount::ZERO, |total, output| total.checked_add(output.amount).unwrap_or(Amount::MAX_SUPPLY))
+  }
+
+  pub fn clear(&mut self) {
+    self.outputs.clear();
+    self.by_owner.clear();
+  }
+}
+
+/// Pick unspent outputs worth at least `amount`, returning them with the
+/// change that has to be sent back. `None` when they do not add up.
+pub fn select_coins(unspent: &[(OutPoint, TxOutput)], amount: Amount) -> Option<(Vec<OutPoint>, Amount)> {
+  let mut selected = vec![];
+  let mut total = Amount::ZERO;
+
+  for (out_point, output) in unspent.iter() {
+    if total >= amount {
+      break;
+    }
+
+    selected.push(out_point.clone());
+    total = total.checked_add(output.amount)?;
+  }
+
+  Some((selected, total.checked_sub(amount)?))
+}
+
+#[cfg(test)]
+mod test {
+  use super::{OutPoint, TxOutput, UtxoTransaction};
+  use crate::core::{amount::Amount, error::TransactionError, wallet::Wallet};
+
+  // Same key as the account transaction vectors in `transaction.rs`.
+  const PRIVATE_KEY: &str = "0101010101010101010101010101010101010101010101010101010101010101";
+  const PUBLIC_KEY: &str = "6ff03b949241ce1dadd43519e6960e0a85b41a69a05c328103aa2bce1594ca16\
+    3c4f753a55bf01dc53f6c0b0c7eee78b40c6ff7d25a96e2282b989cef71c144a";
+  const SENDER: &str = "1Ji9MaGamXWmq7MMAvuXdgaACgTRHzPZ6W";
+  const RECEIVER: &str = "1BoatSLRHtKNngkdXEeobR76b53LETtpyT";
+
+  #[test]
+  fn test_utxo_signing_vectors() {
+    let wallet = Wallet::new_from(PUBLIC_KEY, PRIVATE_KEY, SENDER).unwrap();
+    let inputs = vec![OutPoint { txid: "ab".repeat(32), index: 1 }];
+    let outputs = vec![TxOutput { address: RECEIVER.to_string(), amount: "1.5".parse::<Amount>().unwrap() }];
+    let transaction = wallet.sign_utxo_transaction(inputs, outputs, "mainnet");
+
+    assert!(transaction.signing_payload().starts_with(UtxoTransaction::SIGNING_DOMAIN));
+    assert_eq!(hex::encode(transaction.signing_hash()), "ccd330e31b481465039ec580380ef08458d813d55e763b74d31d85b3698d6282");
+    assert_eq!(
+      transaction.inputs[0].signature,
+      concat!(
+        "75a929235a17183f0bfdad1ff3ea95d5593e93c15aa56bcc810d64d95ac5b5f7",
+        "ffa95e171967e796383a3ae9a4b3d64bfb636489e4dba640113d18e26bd8f90e",
+      ),
+    );
+    assert_eq!(Wallet::verify_utxo_input(&transaction, &transaction.inputs[0]), Ok(()));
+
+    let mut tampered = transaction.clone();
+    tampered.outputs[0].amount = "2".parse::<Amount>().unwrap();
+    assert_eq!(Wallet::verify_utxo_input(&tampered, &tampered.inputs[0]), Err(TransactionError::InvalidSignature));
+  }
+}
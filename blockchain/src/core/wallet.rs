@@ -1,10 +1,20 @@
-use p256::ecdsa::{signature::Verifier, Signature, SigningKey, VerifyingKey};
-use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
+pub mod hd;
+pub mod keystore;
 
-use crate::utils::keys::generate_keys;
+use p256::ecdsa::{
+  signature::hazmat::{PrehashSigner, PrehashVerifier},
+  Signature, SigningKey, VerifyingKey,
+};
+use serde::{Deserialize, Serialize};
+use crate::utils::{address::{self, AddressFormat}, keys::generate_keys};
 
-use super::transaction::Transaction;
+use super::{
+  amount::Amount,
+  error::{KeyError, TransactionError},
+  network::Network,
+  transaction::Transaction,
+  utxo::{OutPoint, TxInput, TxOutput, UtxoTransaction},
+};
 
 pub struct Wallet {
   private_key: SigningKey,
@@ -17,65 +27,62 @@ pub struct WalletDetails {
   pub public_key: String,
   pub private_key: String,
   pub blockchain_address: String,
+  /// the same address in Bech32 form
+  pub bech32_address: String,
 }
 
 impl Wallet {
-  pub fn new() -> Self {
+  pub fn new(network: Network) -> Self {
     let keys = generate_keys();
 
-    let pub_key_encoded = keys.clone().unwrap().1.to_encoded_point(false);
-
-    let mut address = String::new();
-    let mut gen_address = || {
-      if let (Some(x), Some(y)) = (pub_key_encoded.x(), pub_key_encoded.y()) {
-        let mut pub_key_bytes = Vec::with_capacity(x.len() + y.len());
-  
-        pub_key_bytes.extend_from_slice(x);
+    let address = Self::derive_address(&keys.clone().unwrap().1, network);
 
-        let hash = Sha256::digest(pub_key_bytes);
-
-        let mut hasher = ripemd::Ripemd160::new();
-        hasher.update(&hash);
+    Self {
+      private_key: keys.clone().unwrap().0,
+      public_key: keys.unwrap().1,
+      address,
+    }
+  }
 
-        let mut hash_result = hasher.finalize().to_vec();
-        hash_result.insert(0, 0x00);
+  pub fn from_signing_key(private_key: SigningKey, network: Network) -> Self {
+    let public_key = VerifyingKey::from(&private_key);
+    let address = Self::derive_address(&public_key, network);
 
-        let checksum = &hash[0..4];
+    Self { private_key, public_key, address }
+  }
 
-        let full_hash = [hash_result, checksum.to_vec()].concat();
+  pub fn derive_address(public_key: &VerifyingKey, network: Network) -> String {
+    address::from_public_key(public_key, network, AddressFormat::Base58Check)
+  }
 
-        address = bs58::encode(full_hash).into_string();
-      }
-    };
+  /// Address owning the hex encoded public key used in transactions.
+  pub fn address_of(public_key: &str, network: Network) -> Result<String, KeyError> {
+    Ok(Self::derive_address(&Self::parse_public_key(public_key)?, network))
+  }
 
-    gen_address();
+  fn parse_public_key(public_key: &str) -> Result<VerifyingKey, KeyError> {
+    let mut pub_key_bin = hex::decode(public_key).map_err(|_| KeyError::InvalidHex)?;
+    pub_key_bin.insert(0, 0x04);
 
-    Self {
-      private_key: keys.clone().unwrap().0,
-      public_key: keys.unwrap().1,
-      address,
-    }
+    VerifyingKey::from_sec1_bytes(&pub_key_bin).map_err(|_| KeyError::InvalidPublicKey)
   }
 
   pub fn new_from(
-    public_key: &String,
-    private_key: &String,
-    recipient_address: &String,
-  ) -> Self {
-    let mut public_key_bin = hex::decode(public_key).unwrap();
-    public_key_bin.insert(0, 0x04);
-
-    let verifying_key = VerifyingKey::from_sec1_bytes(&public_key_bin).unwrap();
+    public_key: &str,
+    private_key: &str,
+    recipient_address: &str,
+  ) -> Result<Self, KeyError> {
+    let verifying_key = Self::parse_public_key(public_key)?;
 
-    let private_key_bin = hex::decode(private_key).unwrap();
-    let private_key_bin: [u8; 32] = private_key_bin.try_into().expect("invalid private key provided");
-    let signing_key = SigningKey::from_bytes((&private_key_bin).into()).unwrap();
+    let private_key_bin = hex::decode(private_key).map_err(|_| KeyError::InvalidHex)?;
+    let private_key_bin: [u8; 32] = private_key_bin.try_into().map_err(|_| KeyError::InvalidPrivateKey)?;
+    let signing_key = SigningKey::from_bytes((&private_key_bin).into()).map_err(|_| KeyError::InvalidPrivateKey)?;
 
-    Self {
+    Ok(Self {
       private_key: signing_key,
       public_key: verifying_key,
-      address: recipient_address.clone(),
-    }
+      address: recipient_address.to_string(),
+    })
   }
 
   pub fn private_key(&self) -> String {
@@ -86,72 +93,100 @@ impl Wallet {
     self.address.clone()
   }
 
+  /// The wallet address written in `format`, transactions may use either.
+  pub fn address_in(&self, format: AddressFormat) -> String {
+    address::convert(&self.address, format).unwrap_or_else(|_| self.address.clone())
+  }
+
   pub fn public_key(&self) -> String {
     let key_points = self.public_key.to_encoded_point(false);
 
     if let (Some(x), Some(y)) = (key_points.x(), key_points.y()) {
-      let pub_key_str = hex::encode(x) + hex::encode(y).as_str();
-
-      pub_key_str
+      hex::encode(x) + hex::encode(y).as_str()
     } else {
       String::new()
     }
   }
 
-  pub fn sign_transaction(&self, receiver: String, amount: f64) -> Transaction {
+  pub fn sign_transaction(&self, receiver: String, amount: Amount, nonce: u64, chain_id: &str) -> Transaction {
     let mut trx = Transaction {
       sender: self.address.clone(),
       receiver,
       signature: String::new(),
       public_key: self.public_key(),
       amount,
+      nonce,
+      chain_id: chain_id.to_string(),
     };
 
-    let serialized_trx_str = serde_json::to_string(&trx).unwrap();
-    let serialized_trx_byte = serialized_trx_str.as_bytes();
-
-    let sig: Signature = self.private_key.sign_recoverable(serialized_trx_byte).unwrap().0;
+    let sig: Signature = self.private_key.sign_prehash(&trx.signing_hash()).unwrap();
 
     trx.signature = hex::encode(sig.to_bytes());
 
     trx
   }
 
-  pub fn verify_transaction(transaction: &Transaction) -> bool {
-    let signature = hex::decode(transaction.signature.clone()).unwrap();
+  pub fn verify_transaction(transaction: &Transaction) -> Result<(), TransactionError> {
+    // transactions may come from peers, so malformed encodings fail verification
+    let signature = Self::parse_signature(&transaction.signature)?;
+    let public_key = Self::parse_public_key(&transaction.public_key)?;
 
-    let mut transaction_clone = transaction.clone();
-    transaction_clone.signature = String::new();
+    let (_, sender_hash) = address::decode(&transaction.sender)?;
 
-    let serialized_trx_str = serde_json::to_string(&transaction_clone).unwrap();
-    let serialized_trx_byte = serialized_trx_str.as_bytes();
+    if address::key_hash(&public_key) != sender_hash {
+      return Err(TransactionError::SenderMismatch);
+    }
 
-    let signature_arr: [u8; 64] = signature.try_into().unwrap();
+    public_key
+      .verify_prehash(&transaction.signing_hash(), &signature)
+      .map_err(|_| TransactionError::InvalidSignature)
+  }
 
-    let signature = match Signature::from_bytes(&signature_arr.into()) {
-      Ok(signature) => signature,
-      Err(err) => {
-        eprintln!("{:?}", err);
+  fn parse_signature(signature: &str) -> Result<Signature, TransactionError> {
+    let signature = hex::decode(signature).map_err(|_| TransactionError::InvalidSignature)?;
 
-        return false;
-      }
-    };
+    Signature::from_slice(&signature).map_err(|_| TransactionError::InvalidSignature)
+  }
 
-    let pub_key_str = transaction.public_key.clone();
-    let mut pub_key_bin = hex::decode(pub_key_str).unwrap();
+  /// Spend `inputs`, all owned by this wallet, into `outputs`.
+  pub fn sign_utxo_transaction(&self, inputs: Vec<OutPoint>, outputs: Vec<TxOutput>, chain_id: &str) -> UtxoTransaction {
+    let inputs = inputs
+      .into_iter()
+      .map(|previous_output| TxInput { previous_output, public_key: self.public_key(), signature: String::new() })
+      .collect();
 
-    pub_key_bin.insert(0, 0x04);
+    let mut trx = UtxoTransaction { inputs, outputs, chain_id: chain_id.to_string(), height: 0 };
+
+    let sig: Signature = self.private_key.sign_prehash(&trx.signing_hash()).unwrap();
+
+    for input in trx.inputs.iter_mut() {
+      input.signature = hex::encode(sig.to_bytes());
+    }
+
+    trx
+  }
 
-    let public_key = VerifyingKey::from_sec1_bytes(&pub_key_bin).unwrap();
+  pub fn verify_utxo_input(transaction: &UtxoTransaction, input: &TxInput) -> Result<(), TransactionError> {
+    let signature = Self::parse_signature(&input.signature)?;
+    let public_key = Self::parse_public_key(&input.public_key)?;
 
-    public_key.verify(serialized_trx_byte, &signature).is_ok()
+    public_key
+      .verify_prehash(&transaction.signing_hash(), &signature)
+      .map_err(|_| TransactionError::InvalidSignature)
   }
 
   pub fn get_details(&self) -> WalletDetails {
     WalletDetails {
       public_key: self.public_key(),
       private_key: self.private_key(),
-      blockchain_address: self.address()
+      blockchain_address: self.address(),
+      bech32_address: self.address_in(AddressFormat::Bech32),
     }
   }
+}
+
+impl Default for Wallet {
+  fn default() -> Self {
+    Self::new(Network::default())
+  }
 }
\ No newline at end of file
@@ -0,0 +1,254 @@
+use std::{fmt::Display, str::FromStr};
+
+use bip39::Mnemonic;
+use hmac::{Hmac, Mac};
+use p256::{
+  ecdsa::SigningKey,
+  elliptic_curve::{rand_core::{OsRng, RngCore}, PrimeField},
+  NonZeroScalar, Scalar,
+};
+use sha2::Sha512;
+
+use crate::core::{error::KeyError, network::Network};
+
+use super::Wallet;
+
+/// Child indexes from here on are hardened, written with a trailing `'`.
+pub const HARDENED: u32 = 1 << 31;
+
+/// Key plus chain code as in BIP32, derived the SLIP-10 way since the chain
+/// signs with P-256 rather than secp256k1.
+#[derive(Clone)]
+pub struct ExtendedKey {
+  private_key: SigningKey,
+  chain_code: [u8; 32],
+}
+
+impl ExtendedKey {
+  const SEED_KEY: &'static [u8] = b"Nist256p1 seed";
+
+  pub fn from_seed(seed: &[u8]) -> Self {
+    let mut digest = hmac_sha512(Self::SEED_KEY, seed);
+
+    // SLIP-10: hash again until the left half is a valid private key
+    loop {
+      let (key, chain_code) = digest.split_at(32);
+
+      if let Some(private_key) = Self::scalar(key).and_then(Self::non_zero) {
+        return Self { private_key: SigningKey::from(private_key), chain_code: chain_code.try_into().unwrap() };
+      }
+
+      digest = hmac_sha512(Self::SEED_KEY, &digest);
+    }
+  }
+
+  pub fn derive_child(&self, index: u32) -> Self {
+    let mut data = if index >= HARDENED {
+      [&[0_u8][..], &self.private_key.to_bytes()].concat()
+    } else {
+      self.private_key.verifying_key().to_encoded_point(true).as_bytes().to_vec()
+    };
+    data.extend(index.to_be_bytes());
+
+    loop {
+      let digest = hmac_sha512(&self.chain_code, &data);
+      let (tweak, chain_code) = digest.split_at(32);
+
+      let child = Self::scalar(tweak)
+        .map(|tweak| tweak + self.private_key.as_nonzero_scalar().as_ref())
+        .and_then(Self::non_zero);
+
+      if let Some(child) = child {
+        return Self { private_key: SigningKey::from(child), chain_code: chain_code.try_into().unwrap() };
+      }
+
+      data = [&[1_u8][..], chain_code, &index.to_be_bytes()].concat();
+    }
+  }
+
+  pub fn derive_path(&self, path: &DerivationPath) -> Self {
+    path.0.iter().fold(self.clone(), |key, index| key.derive_child(*index))
+  }
+
+  pub fn private_key(&self) -> &SigningKey {
+    &self.private_key
+  }
+
+  pub fn chain_code(&self) -> &[u8; 32] {
+    &self.chain_code
+  }
+
+  /// `None` when the bytes are not below the curve order.
+  fn scalar(bytes: &[u8]) -> Option<Scalar> {
+    Scalar::from_repr(*p256::FieldBytes::from_slice(bytes)).into()
+  }
+
+  fn non_zero(scalar: Scalar) -> Option<NonZeroScalar> {
+    NonZeroScalar::new(scalar).into()
+  }
+}
+
+fn hmac_sha512(key: &[u8], data: &[u8]) -> [u8; 64] {
+  let mut mac = Hmac::<Sha512>::new_from_slice(key).unwrap();
+  mac.update(data);
+
+  mac.finalize().into_bytes().into()
+}
+
+/// Child indexes from the master key, written like `m/44'/1'/0'/0/3`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DerivationPath(Vec<u32>);
+
+impl FromStr for DerivationPath {
+  type Err = KeyError;
+
+  fn from_str(path: &str) -> Result<Self, Self::Err> {
+    let mut parts = path.split('/');
+
+    if parts.next() != Some("m") {
+      return Err(KeyError::InvalidDerivationPath(path.to_string()));
+    }
+
+    parts
+      .map(|part| {
+        let (number, offset) = match part.strip_suffix('\'') {
+          Some(number) => (number, HARDENED),
+          None => (part, 0),
+        };
+
+        number
+          .parse::<u32>()
+          .ok()
+          .filter(|index| *index < HARDENED)
+          .map(|index| index + offset)
+          .ok_or_else(|| KeyError::InvalidDerivationPath(path.to_string()))
+      })
+      .collect::<Result<_, _>>()
+      .map(Self)
+  }
+}
+
+impl Display for DerivationPath {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "m")?;
+
+    for index in self.0.iter() {
+      match index.checked_sub(HARDENED) {
+        Some(hardened) => write!(f, "/{}'", hardened)?,
+        None => write!(f, "/{}", index)?,
+      }
+    }
+
+    Ok(())
+  }
+}
+
+/// Wallet backed up by a single BIP39 phrase, every receiving address is
+/// derived from it at `m/44'/coin'/0'/0/index`.
+pub struct HdWallet {
+  mnemonic: Mnemonic,
+  master: ExtendedKey,
+  network: Network,
+}
+
+impl HdWallet {
+  /// 128 bits of entropy, a 12 word phrase.
+  const ENTROPY_LEN: usize = 16;
+
+  pub fn generate(network: Network) -> Self {
+    let mut entropy = [0_u8; Self::ENTROPY_LEN];
+    OsRng.fill_bytes(&mut entropy);
+
+    let mnemonic = Mnemonic::from_entropy(&entropy).unwrap();
+
+    Self::from_parts(mnemonic, "", network)
+  }
+
+  /// Recover the wallet behind `phrase`, the passphrase is the optional BIP39
+  /// extension word and is empty for most wallets.
+  pub fn from_mnemonic(phrase: &str, passphrase: &str, network: Network) -> Result<Self, KeyError> {
+    let mnemonic = Mnemonic::parse(phrase).map_err(|_| KeyError::InvalidMnemonic)?;
+
+    Ok(Self::from_parts(mnemonic, passphrase, network))
+  }
+
+  fn from_parts(mnemonic: Mnemonic, passphrase: &str, network: Network) -> Self {
+    let master = ExtendedKey::from_seed(&mnemonic.to_seed(passphrase));
+
+    Self { mnemonic, master, network }
+  }
+
+  pub fn mnemonic(&self) -> String {
+    self.mnemonic.to_string()
+  }
+
+  pub fn network(&self) -> Network {
+    self.network
+  }
+
+  /// Fails for indexes of `HARDENED` and above, which would silently derive
+  /// a hardened child instead.
+  pub fn receiving_path(&self, index: u32) -> Result<DerivationPath, KeyError> {
+    if index >= HARDENED {
+      return Err(KeyError::HardenedIndex(index));
+    }
+
+    Ok(DerivationPath(vec![44 + HARDENED, self.network.coin_type() + HARDENED, HARDENED, 0, index]))
+  }
+
+  /// Wallet for the `index`th receiving address.
+  pub fn wallet(&self, index: u32) -> Result<Wallet, KeyError> {
+    let key = self.master.derive_path(&self.receiving_path(index)?);
+
+    Ok(Wallet::from_signing_key(key.private_key().clone(), self.network))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{DerivationPath, ExtendedKey, HdWallet, HARDENED};
+  use crate::core::{error::KeyError, network::Network};
+
+  fn derive(seed: &ExtendedKey, path: &str) -> (String, String) {
+    let key = seed.derive_path(&path.parse().unwrap());
+
+    (hex::encode(key.chain_code()), hex::encode(key.private_key().to_bytes()))
+  }
+
+  #[test]
+  fn test_slip10_vectors() {
+    // test vector 1 for nist256p1 from SLIP-0010
+    let master = ExtendedKey::from_seed(&hex::decode("000102030405060708090a0b0c0d0e0f").unwrap());
+
+    assert_eq!(derive(&master, "m"), (
+      "beeb672fe4621673f722f38529c07392fecaa61015c80c34f29ce8b41b3cb6ea".to_string(),
+      "612091aaa12e22dd2abef664f8a01a82cae99ad7441b7ef8110424915c268bc2".to_string(),
+    ));
+    assert_eq!(derive(&master, "m/0'"), (
+      "3460cea53e6a6bb5fb391eeef3237ffd8724bf0a40e94943c98b83825342ee11".to_string(),
+      "6939694369114c67917a182c59ddb8cafc3004e63ca5d3b84403ba8613debc0c".to_string(),
+    ));
+    assert_eq!(derive(&master, "m/0'/1"), (
+      "4187afff1aafa8445010097fb99d23aee9f599450c7bd140b6826ac22ba21d0c".to_string(),
+      "284e9d38d07d21e4e281b645089a94f4cf5a5a81369acf151a1c3a57f18b2129".to_string(),
+    ));
+  }
+
+  #[test]
+  fn test_mnemonic_recovers_addresses() {
+    let generated = HdWallet::generate(Network::Testnet);
+    let recovered = HdWallet::from_mnemonic(&generated.mnemonic(), "", Network::Testnet).unwrap();
+
+    assert_eq!(generated.mnemonic().split(' ').count(), 12);
+    assert_eq!(recovered.wallet(3).unwrap().address(), generated.wallet(3).unwrap().address());
+    assert_ne!(recovered.wallet(0).unwrap().address(), recovered.wallet(1).unwrap().address());
+    assert_eq!(recovered.receiving_path(3).unwrap().to_string(), "m/44'/1'/0'/0/3");
+    assert_eq!(recovered.wallet(HARDENED).err(), Some(KeyError::HardenedIndex(HARDENED)));
+
+    let with_passphrase = HdWallet::from_mnemonic(&generated.mnemonic(), "extra", Network::Testnet).unwrap();
+    assert_ne!(with_passphrase.wallet(0).unwrap().address(), generated.wallet(0).unwrap().address());
+
+    assert!(matches!(HdWallet::from_mnemonic("not a phrase", "", Network::Testnet), Err(KeyError::InvalidMnemonic)));
+    assert!("m/1/x'".parse::<DerivationPath>().is_err());
+  }
+}
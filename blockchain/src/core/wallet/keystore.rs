@@ -0,0 +1,273 @@
+use std::{fs::{self, OpenOptions}, io::{self, ErrorKind, Write}, path::Path};
+
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
+use aes_gcm::{
+  aead::{Aead, KeyInit, Payload},
+  Aes256Gcm, Key, Nonce,
+};
+use p256::{ecdsa::SigningKey, elliptic_curve::rand_core::{OsRng, RngCore}};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  core::error::{BlockchainError, KeystoreError},
+  utils::address,
+};
+
+use super::Wallet;
+
+const CIPHER: &str = "aes-256-gcm";
+const KDF: &str = "scrypt";
+const SALT_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+/// Password protected private key, stored as JSON.
+///
+/// The key is encrypted with AES-256-GCM under a key stretched from the
+/// password with scrypt. The address is kept in the clear so tooling can list
+/// keystores without the password, and is authenticated as associated data.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Keystore {
+  pub version: u32,
+  pub address: String,
+  pub crypto: KeystoreCrypto,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeystoreCrypto {
+  pub cipher: String,
+  /// hex encoded, includes the authentication tag
+  pub ciphertext: String,
+  pub nonce: String,
+  pub kdf: String,
+  pub kdfparams: ScryptParams,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScryptParams {
+  pub log_n: u8,
+  pub r: u32,
+  pub p: u32,
+  pub salt: String,
+}
+
+impl Keystore {
+  pub const VERSION: u32 = 1;
+  /// scrypt cost used for new keystores, about 32 MiB of memory
+  pub const LOG_N: u8 = 15;
+  /// highest cost accepted when decrypting, which with `R` bounds memory to
+  /// about 1 GiB
+  const MAX_LOG_N: u8 = 20;
+  /// scrypt block size, the only one accepted since memory grows with it
+  const R: u32 = 8;
+  /// highest scrypt parallelism accepted, each lane costs another full pass
+  const MAX_P: u32 = 4;
+
+  pub fn encrypt(wallet: &Wallet, password: &str) -> Result<Self, KeystoreError> {
+    Self::encrypt_with_cost(wallet, password, Self::LOG_N)
+  }
+
+  pub fn encrypt_with_cost(wallet: &Wallet, password: &str, log_n: u8) -> Result<Self, KeystoreError> {
+    let mut salt = [0_u8; SALT_LEN];
+    let mut nonce = [0_u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let kdfparams = ScryptParams { log_n, r: Self::R, p: 1, salt: hex::encode(salt) };
+    let key = derive_key(password, &kdfparams)?;
+
+    let payload = Payload { msg: &wallet.private_key.to_bytes(), aad: wallet.address.as_bytes() };
+    let ciphertext = Aes256Gcm::new(&key).encrypt(Nonce::from_slice(&nonce), payload).unwrap();
+
+    Ok(Self {
+      version: Self::VERSION,
+      address: wallet.address(),
+      crypto: KeystoreCrypto {
+        cipher: CIPHER.to_string(),
+        ciphertext: hex::encode(ciphertext),
+        nonce: hex::encode(nonce),
+        kdf: KDF.to_string(),
+        kdfparams,
+      },
+    })
+  }
+
+  pub fn decrypt(&self, password: &str) -> Result<Wallet, KeystoreError> {
+    if self.version != Self::VERSION {
+      return Err(KeystoreError::UnsupportedVersion(self.version));
+    }
+
+    if self.crypto.cipher != CIPHER {
+      return Err(KeystoreError::UnsupportedCipher(self.crypto.cipher.clone()));
+    }
+
+    if self.crypto.kdf != KDF {
+      return Err(KeystoreError::UnsupportedKdf(self.crypto.kdf.clone()));
+    }
+
+    let (network, key_hash) = address::decode(&self.address).map_err(|_| KeystoreError::Malformed("address"))?;
+    let key = derive_key(password, &self.crypto.kdfparams)?;
+
+    let nonce = decode_hex(&self.crypto.nonce, "nonce")?;
+    let ciphertext = decode_hex(&self.crypto.ciphertext, "ciphertext")?;
+
+    if nonce.len() != NONCE_LEN {
+      return Err(KeystoreError::Malformed("nonce"));
+    }
+
+    let payload = Payload { msg: &ciphertext, aad: self.address.as_bytes() };
+    let private_key = Aes256Gcm::new(&key)
+      .decrypt(Nonce::from_slice(&nonce), payload)
+      .map_err(|_| KeystoreError::InvalidPassword)?;
+
+    let private_key = SigningKey::from_slice(&private_key).map_err(|_| KeystoreError::Malformed("ciphertext"))?;
+
+    if address::key_hash(private_key.verifying_key()) != key_hash {
+      return Err(KeystoreError::Malformed("address"));
+    }
+
+    let mut wallet = Wallet::from_signing_key(private_key, network);
+    wallet.address = self.address.clone();
+
+    Ok(wallet)
+  }
+
+  pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+    let content = fs::read_to_string(path)?;
+
+    Ok(serde_json::from_str(&content)?)
+  }
+
+  /// Write the keystore readable by its owner only. It goes to a temporary
+  /// file first and is renamed over `path`, so a crash never leaves a
+  /// truncated keystore behind.
+  pub fn to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    // a leftover from an earlier crash may have been created with other permissions
+    match fs::remove_file(&tmp_path) {
+      Err(err) if err.kind() != ErrorKind::NotFound => return Err(err),
+      _ => {}
+    }
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let mut tmp = options.open(&tmp_path)?;
+    tmp.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+    tmp.sync_all()?;
+
+    fs::rename(tmp_path, path)
+  }
+}
+
+impl Wallet {
+  /// Write the wallet to `path` encrypted with `password`.
+  pub fn export_keystore<P: AsRef<Path>>(&self, path: P, password: &str) -> Result<(), BlockchainError> {
+    Ok(Keystore::encrypt(self, password)?.to_file(path)?)
+  }
+
+  pub fn import_keystore<P: AsRef<Path>>(path: P, password: &str) -> Result<Self, BlockchainError> {
+    Ok(Keystore::from_file(path)?.decrypt(password)?)
+  }
+}
+
+fn derive_key(password: &str, params: &ScryptParams) -> Result<Key<Aes256Gcm>, KeystoreError> {
+  // bounded before calling scrypt, so a crafted file cannot exhaust memory or CPU
+  if params.log_n > Keystore::MAX_LOG_N || params.r != Keystore::R || params.p == 0 || params.p > Keystore::MAX_P {
+    return Err(KeystoreError::InvalidKdfParams);
+  }
+
+  let salt = decode_hex(&params.salt, "salt")?;
+  let params = scrypt::Params::new(params.log_n, params.r, params.p, 32).map_err(|_| KeystoreError::InvalidKdfParams)?;
+
+  let mut key = Key::<Aes256Gcm>::default();
+  scrypt::scrypt(password.as_bytes(), &salt, &params, &mut key).map_err(|_| KeystoreError::InvalidKdfParams)?;
+
+  Ok(key)
+}
+
+fn decode_hex(value: &str, field: &'static str) -> Result<Vec<u8>, KeystoreError> {
+  hex::decode(value).map_err(|_| KeystoreError::Malformed(field))
+}
+
+#[cfg(test)]
+mod test {
+  use std::fs;
+
+  use super::Keystore;
+  use crate::core::{error::KeystoreError, network::Network, wallet::Wallet};
+
+  #[test]
+  fn test_keystore_roundtrip() {
+    let wallet = Wallet::new(Network::Testnet);
+    let keystore = Keystore::encrypt_with_cost(&wallet, "correct horse", 4).unwrap();
+
+    let json = serde_json::to_string(&keystore).unwrap();
+    assert!(!json.contains(&wallet.private_key()));
+
+    let restored = serde_json::from_str::<Keystore>(&json).unwrap().decrypt("correct horse").unwrap();
+    assert_eq!(restored.private_key(), wallet.private_key());
+    assert_eq!(restored.address(), wallet.address());
+
+    assert_eq!(keystore.decrypt("wrong horse").err(), Some(KeystoreError::InvalidPassword));
+
+    let mut tampered = keystore.clone();
+    tampered.address = Wallet::new(Network::Testnet).address();
+    assert_eq!(tampered.decrypt("correct horse").err(), Some(KeystoreError::InvalidPassword));
+
+    let mut future = keystore;
+    future.version = 2;
+    assert_eq!(future.decrypt("correct horse").err(), Some(KeystoreError::UnsupportedVersion(2)));
+  }
+
+  #[test]
+  fn test_oversized_scrypt_params_are_rejected() {
+    let keystore = Keystore::encrypt_with_cost(&Wallet::new(Network::Testnet), "correct horse", 4).unwrap();
+
+    let mut large_r = keystore.clone();
+    large_r.crypto.kdfparams.r = 1 << 16;
+    assert_eq!(large_r.decrypt("correct horse").err(), Some(KeystoreError::InvalidKdfParams));
+
+    let mut large_p = keystore.clone();
+    large_p.crypto.kdfparams.p = u32::MAX;
+    assert_eq!(large_p.decrypt("correct horse").err(), Some(KeystoreError::InvalidKdfParams));
+
+    let mut large_n = keystore;
+    large_n.crypto.kdfparams.log_n = 40;
+    assert_eq!(large_n.decrypt("correct horse").err(), Some(KeystoreError::InvalidKdfParams));
+  }
+  #[test]
+  fn test_new_keystores_reject_oversized_cost() {
+    let wallet = Wallet::new(Network::Testnet);
+
+    let result = Keystore::encrypt_with_cost(&wallet, "correct horse", Keystore::MAX_LOG_N + 1);
+
+    assert_eq!(result.err(), Some(KeystoreError::InvalidKdfParams));
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn test_to_file_replaces_with_owner_only_file() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = std::env::temp_dir().join(format!("keystore-{}.json", std::process::id()));
+    fs::write(&path, "old").unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+    let wallet = Wallet::new(Network::Testnet);
+    Keystore::encrypt_with_cost(&wallet, "correct horse", 4).unwrap().to_file(&path).unwrap();
+
+    assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    assert_eq!(Keystore::from_file(&path).unwrap().decrypt("correct horse").unwrap().address(), wallet.address());
+
+    fs::remove_file(path).unwrap();
+  }
+}
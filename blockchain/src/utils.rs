@@ -1,3 +1,5 @@
 pub mod hash;
 pub mod serializable;
-pub mod keys;
\ No newline at end of file
+pub mod keys;
+pub mod merkle;
+pub mod address;
\ No newline at end of file
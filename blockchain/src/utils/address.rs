@@ -0,0 +1,208 @@
+use bech32::{primitives::decode::CheckedHrpstring, Bech32m, Hrp};
+use p256::ecdsa::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::core::{error::AddressError, network::Network};
+
+const HASH_LEN: usize = 20;
+const CHECKSUM_LEN: usize = 4;
+
+/// `ripemd160(sha256(sec1 key))`, what every address format encodes.
+pub type KeyHash = [u8; HASH_LEN];
+
+/// How an address is written down. Both formats of the same key decode to the
+/// same `KeyHash`, which the ledger keeps balances under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AddressFormat {
+  /// `base58(version | hash | checksum)` where the checksum is the first four
+  /// bytes of `sha256(sha256(version | hash))`
+  #[default]
+  Base58Check,
+  /// Bech32m of the hash with the network's human readable prefix
+  Bech32,
+}
+
+/// Hash of the uncompressed SEC1 encoding of `public_key`.
+pub fn key_hash(public_key: &VerifyingKey) -> KeyHash {
+  ripemd::Ripemd160::digest(Sha256::digest(public_key.to_encoded_point(false).as_bytes())).into()
+}
+
+pub fn encode(key_hash: &KeyHash, network: Network, format: AddressFormat) -> String {
+  match format {
+    AddressFormat::Base58Check => {
+      let mut payload = vec![network.address_version()];
+      payload.extend(key_hash);
+
+      let checksum = checksum(&payload);
+      payload.extend(checksum);
+
+      bs58::encode(payload).into_string()
+    }
+    AddressFormat::Bech32 => {
+      // prefixes are constants checked by the network tests and the hash is
+      // far below the length limit, so encoding cannot fail
+      bech32::encode::<Bech32m>(Hrp::parse_unchecked(network.bech32_hrp()), key_hash).unwrap()
+    }
+  }
+}
+
+pub fn from_public_key(public_key: &VerifyingKey, network: Network, format: AddressFormat) -> String {
+  encode(&key_hash(public_key), network, format)
+}
+
+/// Network and key hash of an address in either format, checking its
+/// encoding and checksum. Bech32 addresses need the Bech32m checksum and
+/// exactly the prefix of their network.
+pub fn decode(address: &str) -> Result<(Network, KeyHash), AddressError> {
+  let lowercase = address.to_lowercase();
+
+  let bech32_network = Network::ALL
+    .into_iter()
+    .find(|network| lowercase.starts_with(&format!("{}1", network.bech32_hrp())));
+
+  let (network, hash) = match bech32_network {
+    Some(network) => {
+      let checked = CheckedHrpstring::new::<Bech32m>(address).map_err(|_| AddressError::InvalidBech32)?;
+
+      if checked.hrp().to_lowercase() != network.bech32_hrp() {
+        return Err(AddressError::InvalidBech32);
+      }
+
+      (network, checked.byte_iter().collect())
+    }
+    None => {
+      let decoded = bs58::decode(address).into_vec().map_err(|_| AddressError::InvalidBase58)?;
+
+      if decoded.len() != 1 + HASH_LEN + CHECKSUM_LEN {
+        return Err(AddressError::InvalidLength(decoded.len()));
+      }
+
+      let network = Network::from_address_version(decoded[0]).ok_or(AddressError::UnsupportedVersion(decoded[0]))?;
+
+      let (payload, found) = decoded.split_at(1 + HASH_LEN);
+
+      if checksum(payload) != found {
+        return Err(AddressError::ChecksumMismatch);
+      }
+
+      (network, payload[1..].to_vec())
+    }
+  };
+
+  let hash = hash.as_slice().try_into().map_err(|_| AddressError::InvalidLength(hash.len()))?;
+
+  Ok((network, hash))
+}
+
+/// Check an address in either format and return the network it belongs to.
+pub fn validate(address: &str) -> Result<Network, AddressError> {
+  decode(address).map(|(network, _)| network)
+}
+
+/// Like `validate`, but also refuse addresses of any other network.
+pub fn validate_for(address: &str, network: Network) -> Result<(), AddressError> {
+  let found = validate(address)?;
+
+  if found != network {
+    return Err(AddressError::WrongNetwork { expected: network, found });
+  }
+
+  Ok(())
+}
+
+/// The same address written in `format`.
+pub fn convert(address: &str, format: AddressFormat) -> Result<String, AddressError> {
+  let (network, hash) = decode(address)?;
+
+  Ok(encode(&hash, network, format))
+}
+
+/// What the ledger keeps balances, nonces and outputs under, so every format
+/// of an address shares them. Decoded once when an entry is recorded instead
+/// of on every lookup.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LedgerKey {
+  Key(Network, KeyHash),
+  /// anything that is not a valid address, like the mining sender
+  Other(String),
+}
+
+pub fn ledger_key(address: &str) -> LedgerKey {
+  match decode(address) {
+    Ok((network, hash)) => LedgerKey::Key(network, hash),
+    Err(_) => LedgerKey::Other(address.to_string()),
+  }
+}
+
+fn checksum(payload: &[u8]) -> [u8; CHECKSUM_LEN] {
+  Sha256::digest(Sha256::digest(payload))[..CHECKSUM_LEN].try_into().unwrap()
+}
+
+#[cfg(test)]
+mod test {
+  use bech32::{Bech32, Bech32m, Hrp};
+  use p256::ecdsa::SigningKey;
+
+  use super::{convert, decode, from_public_key, key_hash, ledger_key, validate, validate_for, AddressFormat, LedgerKey};
+  use crate::core::{error::AddressError, network::Network};
+
+  #[test]
+  fn test_address_checksum() {
+    let signing_key = SigningKey::from_bytes((&[1u8; 32]).into()).unwrap();
+    let address = from_public_key(signing_key.verifying_key(), Network::Mainnet, AddressFormat::Base58Check);
+
+    assert!(address.starts_with('1'));
+    assert_eq!(validate(&address), Ok(Network::Mainnet));
+
+    let mut typo = address.clone().into_bytes();
+    typo[5] = if typo[5] == b'2' { b'3' } else { b'2' };
+    assert_eq!(validate(&String::from_utf8(typo).unwrap()), Err(AddressError::ChecksumMismatch));
+
+    assert_eq!(validate("0OIl"), Err(AddressError::InvalidBase58));
+    assert_eq!(validate("1111"), Err(AddressError::InvalidLength(4)));
+
+    let testnet = from_public_key(signing_key.verifying_key(), Network::Testnet, AddressFormat::Base58Check);
+    assert_ne!(testnet, address);
+    assert_eq!(
+      validate_for(&testnet, Network::Mainnet),
+      Err(AddressError::WrongNetwork { expected: Network::Mainnet, found: Network::Testnet }),
+    );
+  }
+
+  #[test]
+  fn test_bech32_shares_ledger_key_with_base58() {
+    let signing_key = SigningKey::from_bytes((&[1u8; 32]).into()).unwrap();
+    let base58 = from_public_key(signing_key.verifying_key(), Network::Testnet, AddressFormat::Base58Check);
+    let bech32 = from_public_key(signing_key.verifying_key(), Network::Testnet, AddressFormat::Bech32);
+
+    assert!(bech32.starts_with("tqt1"));
+    assert_eq!(decode(&bech32), decode(&base58));
+    assert_eq!(decode(&bech32.to_uppercase()), decode(&base58));
+    assert_eq!(ledger_key(&bech32), ledger_key(&base58));
+    assert_eq!(convert(&base58, AddressFormat::Bech32), Ok(bech32.clone()));
+    assert_eq!(ledger_key("not an address"), LedgerKey::Other("not an address".to_string()));
+
+    let mut typo = bech32.into_bytes();
+    let last = typo.len() - 1;
+    typo[last] = if typo[last] == b'q' { b'p' } else { b'q' };
+    assert_eq!(validate(&String::from_utf8(typo).unwrap()), Err(AddressError::InvalidBech32));
+  }
+  #[test]
+  fn test_bech32_needs_exact_prefix_and_bech32m() {
+    let signing_key = SigningKey::from_bytes((&[1u8; 32]).into()).unwrap();
+    let hash = key_hash(signing_key.verifying_key());
+
+    let longer_prefix = bech32::encode::<Bech32m>(Hrp::parse("tq1evil").unwrap(), &hash).unwrap();
+    assert!(longer_prefix.starts_with("tq1evil1"));
+    assert_eq!(validate(&longer_prefix), Err(AddressError::InvalidBech32));
+    assert_eq!(ledger_key(&longer_prefix), LedgerKey::Other(longer_prefix.clone()));
+
+    let legacy = bech32::encode::<Bech32>(Hrp::parse(Network::Mainnet.bech32_hrp()).unwrap(), &hash).unwrap();
+    assert_eq!(validate(&legacy), Err(AddressError::InvalidBech32));
+
+    let bech32m = bech32::encode::<Bech32m>(Hrp::parse(Network::Mainnet.bech32_hrp()).unwrap(), &hash).unwrap();
+    assert_eq!(decode(&bech32m), Ok((Network::Mainnet, hash)));
+  }
+}
@@ -0,0 +1,129 @@
+use serde::{Deserialize, Serialize};
+
+use super::hash::hash;
+
+// leaves and inner nodes are hashed under different prefixes so an inner node
+// can never be passed off as a transaction
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+fn hash_leaf(leaf: &[u8]) -> Vec<u8> {
+  hash([&[LEAF_PREFIX], leaf].concat())
+}
+
+fn hash_node(left: &[u8], right: &[u8]) -> Vec<u8> {
+  hash([&[NODE_PREFIX], left, right].concat())
+}
+
+/// Which side of the running hash a sibling sits on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+  Left,
+  Right,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofStep {
+  pub side: Side,
+  pub hash: Vec<u8>,
+}
+
+/// Path from one leaf up to the merkle root, listing the sibling hash at every
+/// level where the leaf's branch has one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProof {
+  pub index: usize,
+  pub steps: Vec<ProofStep>,
+}
+
+impl MerkleProof {
+  /// Root reached by hashing `leaf` up along the proof.
+  pub fn root(&self, leaf: &[u8]) -> Vec<u8> {
+    self.steps.iter().fold(hash_leaf(leaf), |current, step| match step.side {
+      Side::Left => hash_node(&step.hash, &current),
+      Side::Right => hash_node(&current, &step.hash),
+    })
+  }
+
+  pub fn verify(&self, leaf: &[u8], root: &[u8]) -> bool {
+    self.root(leaf) == root
+  }
+}
+
+/// Hashes of every level of the tree, leaves first. A node without a sibling
+/// is carried up to the next level unchanged.
+fn levels(leaves: &[Vec<u8>]) -> Vec<Vec<Vec<u8>>> {
+  let mut levels = vec![leaves.iter().map(|leaf| hash_leaf(leaf)).collect::<Vec<_>>()];
+
+  while levels.last().unwrap().len() > 1 {
+    let next = levels
+      .last()
+      .unwrap()
+      .chunks(2)
+      .map(|pair| match pair {
+        [left, right] => hash_node(left, right),
+        [single] => single.clone(),
+        _ => unreachable!(),
+      })
+      .collect();
+
+    levels.push(next);
+  }
+
+  levels
+}
+
+/// Merkle root over `leaves`, all zeros for an empty list.
+pub fn merkle_root(leaves: &[Vec<u8>]) -> Vec<u8> {
+  if leaves.is_empty() {
+    return vec![0_u8; 32];
+  }
+
+  levels(leaves).pop().unwrap().remove(0)
+}
+
+pub fn merkle_proof(leaves: &[Vec<u8>], index: usize) -> Option<MerkleProof> {
+  if index >= leaves.len() {
+    return None;
+  }
+
+  let mut steps = vec![];
+  let mut position = index;
+
+  for level in levels(leaves).iter() {
+    let sibling = position ^ 1;
+
+    if let Some(hash) = level.get(sibling) {
+      let side = if sibling < position { Side::Left } else { Side::Right };
+
+      steps.push(ProofStep { side, hash: hash.clone() });
+    }
+
+    position /= 2;
+  }
+
+  Some(MerkleProof { index, steps })
+}
+
+#[cfg(test)]
+mod test {
+  use super::{merkle_proof, merkle_root};
+
+  #[test]
+  fn test_proofs_verify_against_root() {
+    for count in 1..=7_u8 {
+      let leaves: Vec<Vec<u8>> = (0..count).map(|leaf| vec![leaf; 3]).collect();
+      let root = merkle_root(&leaves);
+
+      for (index, leaf) in leaves.iter().enumerate() {
+        let proof = merkle_proof(&leaves, index).unwrap();
+
+        assert!(proof.verify(leaf, &root));
+        assert!(!proof.verify(b"forged", &root));
+      }
+
+      assert_eq!(merkle_proof(&leaves, leaves.len()), None);
+    }
+  }
+}
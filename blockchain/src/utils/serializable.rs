@@ -1,4 +1,81 @@
+use crate::core::error::DecodeError;
+
+/// Binary record format. Every record starts with its `VERSION` byte so the
+/// layout can change without old bytes being misread.
 pub trait Serializable <T> {
+  /// Leading byte of every record, bumped whenever the layout changes.
+  const VERSION: u8;
+
   fn serialize(&self) -> Vec<u8>;
-  fn deserialize(bytes: Vec<u8>) -> T;
-}
\ No newline at end of file
+  /// Counterpart of `serialize`, safe to call on bytes coming from peers.
+  fn deserialize(bytes: &[u8]) -> Result<T, DecodeError>;
+}
+
+/// Longest field a record may declare, anything above is rejected before the
+/// length is ever used.
+pub const MAX_FIELD_LEN: usize = 1 << 20;
+
+/// Append `field` prefixed with its length, the layout every record uses.
+pub fn write_field(serialized: &mut Vec<u8>, field: &[u8]) {
+  serialized.extend((field.len() as u64).to_be_bytes());
+  serialized.extend(field);
+}
+
+/// Bounds checked reader over length prefixed fields written by `write_field`.
+pub struct FieldReader<'a> {
+  bytes: &'a [u8],
+  pos: usize,
+}
+
+impl<'a> FieldReader<'a> {
+  pub fn new(bytes: &'a [u8]) -> Self {
+    Self { bytes, pos: 0 }
+  }
+
+  pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+    let end = self.pos.checked_add(len).ok_or(DecodeError::UnexpectedEnd)?;
+    let field = self.bytes.get(self.pos..end).ok_or(DecodeError::UnexpectedEnd)?;
+    self.pos = end;
+
+    Ok(field)
+  }
+
+  /// Check the leading version byte of a record.
+  pub fn read_version(&mut self, expected: u8) -> Result<(), DecodeError> {
+    match self.read_bytes(1)?[0] {
+      version if version == expected => Ok(()),
+      version => Err(DecodeError::UnsupportedVersion(version)),
+    }
+  }
+
+  pub fn read_field(&mut self) -> Result<&'a [u8], DecodeError> {
+    let len = u64::from_be_bytes(self.read_bytes(8)?.try_into().unwrap());
+
+    match usize::try_from(len) {
+      Ok(len) if len <= MAX_FIELD_LEN => self.read_bytes(len),
+      _ => Err(DecodeError::FieldTooLong(len)),
+    }
+  }
+
+  /// Field of exactly `N` bytes, e.g. a big endian integer.
+  pub fn read_array<const N: usize>(&mut self, name: &'static str) -> Result<[u8; N], DecodeError> {
+    self.read_field()?.try_into().map_err(|_| DecodeError::InvalidField(name))
+  }
+
+  pub fn read_u64(&mut self, name: &'static str) -> Result<u64, DecodeError> {
+    Ok(u64::from_be_bytes(self.read_array(name)?))
+  }
+
+  pub fn read_string(&mut self, name: &'static str) -> Result<String, DecodeError> {
+    String::from_utf8(self.read_field()?.to_vec()).map_err(|_| DecodeError::InvalidField(name))
+  }
+
+  /// Fail unless every byte of the record has been read.
+  pub fn finish(&self) -> Result<(), DecodeError> {
+    if self.pos != self.bytes.len() {
+      return Err(DecodeError::TrailingBytes);
+    }
+
+    Ok(())
+  }
+}